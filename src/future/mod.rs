@@ -0,0 +1,113 @@
+//! Interoperability between green threads and `async` code.
+//!
+//! Green threads can run futures to completion with [`block_on`], which only
+//! parks the calling green thread while the future is pending, letting the rest
+//! of the green threads on the OS thread run in the meantime.
+
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+pub(crate) use waker::ThreadWaker;
+
+use pneuma::runtime;
+
+mod waker;
+
+/// Runs a future to completion on the current green thread.
+///
+/// The future is polled with a [`Waker`] that unparks the current green thread,
+/// which is parked while the future is pending. Only the calling green thread
+/// waits for the future, other green threads keep running in the meantime. The
+/// waker may be used from other OS threads, in which case the wake up is
+/// forwarded to this thread's runtime.
+///
+/// This function can be called from any green thread, as well as from the OS
+/// thread itself.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// let handle = thread::spawn(|| {
+///     pneuma::block_on(async { 1 + 1 })
+/// });
+///
+/// assert_eq!(handle.join(), 2);
+/// ```
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let rt = runtime::current();
+    let waker = ThreadWaker::new(rt.remote.clone());
+    // The waker must not outlive the current thread while active.
+    let thread = pneuma::thread::current();
+    let _guard = Deactivate(&waker);
+
+    let mut future = pin!(future);
+    let std_waker = Waker::from(waker.clone());
+    let mut cx = Context::from_waker(&std_waker);
+    loop {
+        if let Poll::Ready(out) = future.as_mut().poll(&mut cx) {
+            return out;
+        }
+        thread.set_waiting_on(Some("block_on"));
+        while !waker.take_woken() {
+            rt.park();
+        }
+        thread.set_waiting_on(None);
+    }
+}
+
+/// Deactivates the waker once `block_on` returns or unwinds.
+struct Deactivate<'a>(&'a ThreadWaker);
+
+impl Drop for Deactivate<'_> {
+    fn drop(&mut self) {
+        self.0.deactivate();
+    }
+}
+
+#[test]
+fn block_on_is_woken_from_another_os_thread() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Signal {
+        ready: AtomicBool,
+        waker: Mutex<Option<Waker>>,
+    }
+
+    struct Wait(Arc<Signal>);
+
+    impl Future for Wait {
+        type Output = ();
+
+        fn poll(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            *self.0.waker.lock().unwrap() = Some(cx.waker().clone());
+            if self.0.ready.load(Ordering::Acquire) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    let signal = Arc::new(Signal::default());
+    let remote = signal.clone();
+    let os_thread = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(20));
+        remote.ready.store(true, Ordering::Release);
+        if let Some(waker) = remote.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    });
+
+    // other green threads keep running while the future is pending
+    let other = pneuma::thread::spawn(|| 1);
+    block_on(Wait(signal));
+    assert!(other.is_finished());
+    assert_eq!(other.join(), 1);
+    os_thread.join().unwrap();
+}
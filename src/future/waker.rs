@@ -0,0 +1,86 @@
+use std::mem::ManuallyDrop;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::Wake;
+
+use pneuma::runtime::Remote;
+use pneuma::thread::{self, RcContext, Thread};
+
+/// A waker that unparks a green thread.
+///
+/// Wakers must be `Send` and `Sync`, but green threads can only be unparked
+/// from their own OS thread. Because of this, the waker doesn't own its
+/// thread: it is only valid while the thread is [active](ThreadWaker::new),
+/// and wake ups from other OS threads are forwarded to the thread's runtime.
+pub(crate) struct ThreadWaker {
+    woken: AtomicBool,
+    /// Whether the thread is still waiting to be woken up. This is only
+    /// modified from the thread's OS thread.
+    active: AtomicBool,
+    /// The context of the green thread, only accessed from its OS thread
+    /// while the waker is active.
+    context: usize,
+    owner: std::thread::ThreadId,
+    remote: Arc<Remote>,
+}
+
+impl ThreadWaker {
+    /// Creates an active waker for the current green thread.
+    ///
+    /// The caller must keep the current thread alive until the waker is
+    /// [deactivated](ThreadWaker::deactivate).
+    pub fn new(remote: Arc<Remote>) -> Arc<ThreadWaker> {
+        remote.register_waker();
+        Arc::new(ThreadWaker {
+            woken: AtomicBool::new(false),
+            active: AtomicBool::new(true),
+            context: thread::current().0 .0.as_ptr() as usize,
+            owner: std::thread::current().id(),
+            remote,
+        })
+    }
+
+    /// Returns whether the waker was woken up, resetting it.
+    pub fn take_woken(&self) -> bool {
+        self.woken.swap(false, Ordering::Acquire)
+    }
+
+    /// Stops the waker from unparking its thread. This must be called from the
+    /// thread's OS thread before the thread is dropped.
+    pub fn deactivate(&self) {
+        if self.active.swap(false, Ordering::Relaxed) {
+            self.remote.deregister_waker();
+        }
+    }
+
+    /// Unparks the green thread if the waker is still active. This must only
+    /// be called from the thread's OS thread.
+    pub(crate) fn unpark(&self) {
+        debug_assert_eq!(std::thread::current().id(), self.owner);
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+        // SAFETY: the thread is kept alive while the waker is active.
+        let context = unsafe { NonNull::new_unchecked(self.context as *mut _) };
+        let thread = ManuallyDrop::new(Thread(RcContext(context)));
+        thread.unpark();
+    }
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if self.woken.swap(true, Ordering::Release) {
+            return;
+        }
+        if std::thread::current().id() == self.owner {
+            self.unpark();
+        } else {
+            self.remote.push(self.clone());
+        }
+    }
+}
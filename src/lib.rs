@@ -8,10 +8,13 @@
 extern crate self as pneuma;
 
 // mod runtime;
-mod runtime;
+pub mod future;
+pub mod runtime;
+pub mod sync;
 mod sys;
 pub mod thread;
 
+pub use future::block_on;
 pub use thread::globals::current;
 
 #[test]
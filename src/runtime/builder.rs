@@ -0,0 +1,220 @@
+use std::io;
+use std::rc::Rc;
+use std::time::Duration;
+
+use super::scheduler::{Fifo, Scheduler};
+use super::{DeadlockPolicy, Hooks};
+
+/// Configures the runtime of the current OS thread before it starts.
+///
+/// The runtime of an OS thread is created the first time it is used, with the
+/// default configuration. To configure it, a `Builder` must be installed with
+/// [`install`](Builder::install) before anything else uses pneuma on that
+/// OS thread.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::runtime::{self, scheduler::Prioritized};
+/// use pneuma::thread;
+///
+/// runtime::Builder::new()
+///     .scheduler(Prioritized::default())
+///     .max_threads(Some(128))
+///     .install()
+///     .unwrap();
+///
+/// thread::spawn(|| println!("hello")).join();
+/// ```
+pub struct Builder {
+    pub(crate) scheduler: Box<dyn Scheduler>,
+    pub(crate) max_threads: Option<usize>,
+    pub(crate) quantum: Option<Duration>,
+    pub(crate) watchdog: Option<Duration>,
+    pub(crate) budget: Option<u32>,
+    pub(crate) deadlock_policy: DeadlockPolicy,
+    pub(crate) hooks: Option<Rc<dyn Hooks>>,
+    pub(crate) chain_panic_hook: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+impl Builder {
+    /// Creates the default configuration, from which configuration methods
+    /// can be chained.
+    pub fn new() -> Builder {
+        Builder {
+            scheduler: Box::new(Fifo::default()),
+            max_threads: None,
+            quantum: None,
+            watchdog: None,
+            budget: Some(128),
+            deadlock_policy: DeadlockPolicy::Panic,
+            hooks: None,
+            chain_panic_hook: false,
+        }
+    }
+
+    /// Sets the scheduler that decides which thread runs next.
+    ///
+    /// The default is [`Fifo`], which ignores the priority of the threads, so
+    /// [`Prioritized`](super::scheduler::Prioritized) must be installed for
+    /// priorities to take effect.
+    pub fn scheduler(self, scheduler: impl Scheduler + 'static) -> Self {
+        Self {
+            scheduler: Box::new(scheduler),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of green threads that can be alive at the same
+    /// time. See [`set_max_threads`](super::set_max_threads).
+    pub fn max_threads(self, max_threads: Option<usize>) -> Self {
+        Self {
+            max_threads,
+            ..self
+        }
+    }
+
+    /// Enables time slice preemption, making green threads yield at their
+    /// next [`checkpoint`] once they used `quantum` of CPU time.
+    ///
+    /// The quantum is measured with a CPU time timer of the OS thread, which
+    /// signals it with `SIGVTALRM`. The signal handler only sets a flag, so
+    /// the threads are only preempted at checkpoints, and long running loops
+    /// should call [`checkpoint`] regularly. The time slice of a thread is
+    /// approximate: the timer isn't reset when threads are switched, so a
+    /// thread may be asked to yield earlier than `quantum`.
+    ///
+    /// Preemption is disabled by default, and is only supported on Linux.
+    ///
+    /// [`checkpoint`]: crate::thread::checkpoint
+    pub fn preemption(self, quantum: Option<Duration>) -> Self {
+        Self { quantum, ..self }
+    }
+
+    /// Enables a watchdog that logs the green threads that have been running
+    /// for longer than `limit` without yielding.
+    ///
+    /// The watchdog runs on its own OS thread, so it also reports the threads
+    /// that are stuck and never reach a [`checkpoint`]. Each slice is only
+    /// reported once. The watchdog is disabled by default.
+    ///
+    /// [`checkpoint`]: crate::thread::checkpoint
+    pub fn watchdog(self, limit: Option<Duration>) -> Self {
+        Self {
+            watchdog: limit,
+            ..self
+        }
+    }
+
+    /// Sets the number of operations a green thread can perform before it
+    /// yields, or disables the budget if `budget` is `None`.
+    ///
+    /// Every pneuma synchronization operation, such as locking a
+    /// [`Mutex`](crate::sync::Mutex) or sending on a channel, consumes one unit
+    /// of the budget of the current thread, including the operations that never
+    /// park, like `try_lock` or sending on an unbounded channel. Once the
+    /// budget is exhausted, the operation yields before it starts, so a thread
+    /// whose operations are always ready can't monopolise the OS thread. The
+    /// budget is refilled every time the thread is switched in. See
+    /// [`consume_budget`](crate::thread::consume_budget).
+    ///
+    /// These yields never unwind threads cancelled with
+    /// [`Cancel::Unwind`](crate::thread::Cancel::Unwind), so an operation that
+    /// completes without parking isn't a cancellation point.
+    ///
+    /// The default budget is 128 operations.
+    pub fn budget(self, budget: Option<u32>) -> Self {
+        Self { budget, ..self }
+    }
+
+    /// Sets what the runtime does when it detects a deadlock.
+    ///
+    /// The report lists the parked threads and the primitive each of them is
+    /// waiting on. The default is [`DeadlockPolicy::Panic`].
+    pub fn on_deadlock(self, deadlock_policy: DeadlockPolicy) -> Self {
+        Self {
+            deadlock_policy,
+            ..self
+        }
+    }
+
+    /// Sets the hooks called on the lifecycle events of the green threads.
+    ///
+    /// See [`Hooks`] for the available events.
+    pub fn hooks(self, hooks: impl Hooks + 'static) -> Self {
+        Self {
+            hooks: Some(Rc::new(hooks)),
+            ..self
+        }
+    }
+
+    /// Makes the panics of green threads call the panic hook that was set
+    /// with [`std::panic::set_hook`] before the first runtime of the process
+    /// started.
+    ///
+    /// The runtime replaces the panic hook to report the name of the green
+    /// thread that panicked, instead of the name of the OS thread. The
+    /// previous hook still gets the panics of the OS threads, but it is only
+    /// called for green threads if this is enabled, after the report, since
+    /// std's default hook would print every panic twice. This is disabled by
+    /// default.
+    pub fn chain_panic_hook(self, chain: bool) -> Self {
+        Self {
+            chain_panic_hook: chain,
+            ..self
+        }
+    }
+
+    /// Starts the runtime of the current OS thread with this configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`AlreadyExists`](io::ErrorKind::AlreadyExists) error if the
+    /// runtime of the current OS thread has already started, and the OS error
+    /// if the preemption timer can't be created.
+    pub fn install(self) -> io::Result<()> {
+        super::globals::install(self)
+    }
+}
+
+#[test]
+fn installed_scheduler_is_used() {
+    use super::scheduler::Fifo;
+    use crate::thread::{self, Thread};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Counting(Fifo, Rc<Cell<usize>>);
+
+    impl Scheduler for Counting {
+        fn push(&mut self, thread: Thread) {
+            self.1.set(self.1.get() + 1);
+            self.0.push(thread);
+        }
+
+        fn pop(&mut self) -> Option<Thread> {
+            self.0.pop()
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    let pushes = Rc::new(Cell::new(0));
+    Builder::new()
+        .scheduler(Counting(Fifo::default(), pushes.clone()))
+        .install()
+        .unwrap();
+    thread::spawn(|| ()).join();
+    assert!(pushes.get() > 0);
+
+    let err = Builder::new().install().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+}
@@ -0,0 +1,124 @@
+use std::fmt::Write;
+
+use crate::thread::context::Lifecycle;
+use crate::thread::registry;
+
+use super::Runtime;
+
+/// What the runtime does when it detects a deadlock, set with
+/// [`Builder::on_deadlock`](super::Builder::on_deadlock).
+///
+/// A runtime is deadlocked when a thread parks while no thread is queued to
+/// run, no timer is pending and no waker can be woken up from another OS
+/// thread, as nothing could ever unpark the parked threads.
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy)]
+#[non_exhaustive]
+pub enum DeadlockPolicy {
+    /// Panics in the thread that parked, with the report as the message.
+    #[default]
+    Panic,
+    /// Prints the report to stderr and aborts the process.
+    Abort,
+    /// Prints the report to stderr once, and lets `park` return like it used
+    /// to, so the threads keep checking their conditions.
+    Log,
+    /// Lets `park` return without reporting anything.
+    Ignore,
+}
+
+impl Runtime {
+    /// Returns whether nothing can unpark the threads of the runtime. This
+    /// must be called once there is no thread queued to run.
+    ///
+    /// Threads left parked when the OS thread exits aren't a deadlock, they
+    /// are just never resumed.
+    pub(crate) fn is_deadlocked(&self) -> bool {
+        self.timers.is_empty() && !self.remote.has_wakers() && !self.shutdown.get()
+    }
+
+    /// Handles a deadlock according to the runtime's policy.
+    pub(crate) fn deadlock(&self) {
+        match self.deadlock_policy {
+            DeadlockPolicy::Ignore => (),
+            DeadlockPolicy::Log => {
+                if !self.deadlock_reported.replace(true) {
+                    eprintln!("{}", self.deadlock_report());
+                }
+            }
+            DeadlockPolicy::Abort => {
+                eprintln!("{}", self.deadlock_report());
+                std::process::abort();
+            }
+            // Panicking while unwinding would abort without the report.
+            DeadlockPolicy::Panic if std::thread::panicking() => {
+                eprintln!("{}", self.deadlock_report());
+                std::process::abort();
+            }
+            DeadlockPolicy::Panic => panic!("{}", self.deadlock_report()),
+        }
+    }
+
+    /// Lists the parked threads and what they are waiting on.
+    fn deadlock_report(&self) -> String {
+        let mut report = String::from(
+            "deadlock detected: every green thread is parked and nothing can unpark them",
+        );
+        registry::for_each(|cx| {
+            if let Lifecycle::Finished | Lifecycle::Taken = cx.lifecycle.get() {
+                return;
+            }
+            let _ = write!(report, "\n  green thread {}", cx.id);
+            if let Some(name) = cx.name() {
+                let _ = write!(report, " '{name}'");
+            }
+            let _ = match cx.waiting_on.get() {
+                Some(what) => write!(report, " is waiting on {what}"),
+                None => write!(report, " is parked"),
+            };
+        });
+        report
+    }
+}
+
+#[test]
+fn deadlock_panics_with_report() {
+    use crate::sync::Mutex;
+    use crate::thread;
+    use std::rc::Rc;
+
+    let mutex = Rc::new(Mutex::new(()));
+    let _guard = mutex.lock().unwrap();
+    let locker = mutex.clone();
+    let handle = thread::Builder::new()
+        .name("locker")
+        .spawn(move || drop(locker.lock()))
+        .unwrap();
+
+    // The thread parks on the mutex while we park joining it.
+    let payload = handle.try_join().unwrap_err();
+    let report = payload.downcast_ref::<String>().unwrap();
+    assert!(report.starts_with("deadlock detected"), "{report}");
+    assert!(report.contains("'locker' is waiting on Mutex"), "{report}");
+    assert!(report.contains("is waiting on JoinHandle"), "{report}");
+}
+
+#[test]
+fn mutex_can_be_locked_after_a_deadlock() {
+    use crate::sync::Mutex;
+    use crate::thread;
+    use std::rc::Rc;
+
+    let mutex = Rc::new(Mutex::new(0));
+    let guard = mutex.lock().unwrap();
+    let locker = mutex.clone();
+    let handle = thread::spawn(move || drop(locker.lock()));
+    assert!(handle.try_join().is_err());
+    drop(guard);
+
+    // The thread that unwound out of `lock` left the queue, so the mutex is
+    // free again instead of being handed off to it.
+    let locker = mutex.clone();
+    let handle = thread::spawn(move || *locker.lock().unwrap() += 1);
+    handle.join();
+    assert_eq!(*mutex.lock().unwrap(), 1);
+}
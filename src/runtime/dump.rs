@@ -0,0 +1,405 @@
+//! Dumps of the green threads of a runtime, to see what a hung runtime is
+//! doing.
+//!
+//! Backtraces are captured by walking the frame pointer chain from the saved
+//! registers of each thread. Release builds omit frame pointers, so they must
+//! be built with `-C force-frame-pointers=yes` for the backtraces to be
+//! complete. The frames are symbolised with `dladdr`, which only knows about
+//! the dynamic symbol table. Binaries should be linked with `-rdynamic` to get
+//! the names of their own functions, otherwise only the addresses are shown,
+//! which can be symbolised offline with tools like `addr2line`.
+
+use std::ffi::CStr;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::sys;
+use crate::thread::context::{Lifecycle, Status};
+use crate::thread::{registry, Context, ThreadId};
+
+use super::{current, Remote, Runtime};
+
+/// The maximum number of frames captured for each thread.
+const MAX_FRAMES: usize = 64;
+
+/// The runtimes of all the OS threads, to be dumped on `SIGUSR1`.
+static RUNTIMES: Mutex<Vec<Weak<Remote>>> = Mutex::new(Vec::new());
+
+/// The write end of the pipe the signal handler wakes the dump thread with.
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// A dump of the green threads of a runtime, returned by [`dump`].
+///
+/// Its [`Display`](fmt::Display) implementation prints every thread with its
+/// backtrace.
+#[derive(Debug, Clone)]
+pub struct Dump {
+    threads: Vec<ThreadDump>,
+}
+
+impl Dump {
+    /// Returns the threads of the runtime, in the order they were created.
+    ///
+    /// The OS thread's own context is included, as it can also park.
+    pub fn threads(&self) -> &[ThreadDump] {
+        &self.threads
+    }
+}
+
+/// The state of a green thread, as captured by [`dump`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ThreadDump {
+    pub id: ThreadId,
+    pub name: Option<String>,
+    pub lifecycle: Lifecycle,
+    /// Whether the thread is queued to run.
+    pub status: Status,
+    /// Whether this is the thread that took the dump.
+    pub running: bool,
+    /// The primitive the thread is parked on, if any.
+    pub waiting_on: Option<&'static str>,
+    /// The size of the thread's stack in bytes, or 0 if it isn't known.
+    pub stack_size: usize,
+    /// The number of bytes of the stack in use, if it is known.
+    pub stack_used: Option<usize>,
+    /// The return addresses of the thread's frames, innermost first.
+    pub backtrace: Vec<Frame>,
+}
+
+/// A frame of a [`ThreadDump`] backtrace.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Frame {
+    /// The return address of the frame.
+    pub ip: usize,
+    /// The demangled name of the function, if it could be found.
+    pub symbol: Option<String>,
+    /// The offset of `ip` from the start of the function.
+    pub offset: usize,
+}
+
+/// Dumps the green threads of the runtime of the current OS thread.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// let handle = thread::Builder::new()
+///     .name("worker")
+///     .spawn(thread::park)
+///     .unwrap();
+/// thread::yield_now();
+///
+/// let dump = pneuma::runtime::dump();
+/// assert!(dump.threads().iter().any(|thread| thread.name.as_deref() == Some("worker")));
+/// eprintln!("{dump}");
+///
+/// handle.thread().unpark();
+/// handle.join();
+/// ```
+pub fn dump() -> Dump {
+    current().dump()
+}
+
+/// Prints a [`dump`] of every runtime of the process to stderr whenever the
+/// process receives `SIGUSR1`.
+///
+/// The signal is forwarded to the runtimes by a helper OS thread, and each
+/// runtime prints its dump the next time it parks or wakes up. A runtime stuck
+/// in a green thread that never parks can't print its dump, but the
+/// [watchdog](super::Builder::watchdog) reports those threads.
+///
+/// This replaces any previous `SIGUSR1` handler.
+///
+/// # Errors
+///
+/// Returns the OS error if the handler can't be installed.
+pub fn dump_on_sigusr1() -> io::Result<()> {
+    static INSTALLED: Mutex<bool> = Mutex::new(false);
+    let mut installed = INSTALLED.lock().unwrap();
+    if *installed {
+        return Ok(());
+    }
+
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let [read, write] = fds;
+    std::thread::Builder::new()
+        .name("pneuma-dump".into())
+        .spawn(move || loop {
+            let mut byte = 0u8;
+            let res = unsafe { libc::read(read, (&mut byte as *mut u8).cast(), 1) };
+            if res < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            if res <= 0 {
+                return;
+            }
+            RUNTIMES
+                .lock()
+                .unwrap()
+                .retain(|remote| match remote.upgrade() {
+                    Some(remote) => {
+                        remote.request_dump();
+                        true
+                    }
+                    None => false,
+                });
+        })?;
+    SIGNAL_PIPE.store(write, Ordering::Relaxed);
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as usize;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    *installed = true;
+    Ok(())
+}
+
+extern "C" fn on_signal(_: libc::c_int) {
+    let fd = SIGNAL_PIPE.load(Ordering::Relaxed);
+    let byte = 0u8;
+    unsafe { libc::write(fd, (&byte as *const u8).cast(), 1) };
+}
+
+/// Registers a runtime to be dumped on `SIGUSR1`.
+pub(crate) fn register(remote: &Arc<Remote>) {
+    RUNTIMES.lock().unwrap().push(Arc::downgrade(remote));
+}
+
+impl Runtime {
+    pub(crate) fn dump(&self) -> Dump {
+        let current = self.executor.current();
+        let mut threads = Vec::new();
+        registry::for_each(|cx| {
+            if let Lifecycle::Finished | Lifecycle::Taken = cx.lifecycle.get() {
+                return;
+            }
+            threads.push(ThreadDump::capture(cx, cx.id == current.id()));
+        });
+        Dump { threads }
+    }
+}
+
+impl ThreadDump {
+    fn capture(cx: &Context, running: bool) -> ThreadDump {
+        let lifecycle = cx.lifecycle.get();
+        // The stack grows down from its middle, see `Stack::bottom`.
+        let (bounds, top) = match lifecycle {
+            Lifecycle::OsThread => match sys::os_stack_bounds() {
+                Some(bounds) => (bounds.clone(), bounds.end),
+                None => (0..0, 0),
+            },
+            _ => {
+                let start = cx.stack.data as usize;
+                (start..start + cx.stack.size, cx.stack.bottom() as usize)
+            }
+        };
+
+        let (sp, backtrace) = if running {
+            let (sp, fp) = frame_registers();
+            (Some(sp), walk(None, fp, &bounds))
+        } else if lifecycle == Lifecycle::New {
+            (None, Vec::new())
+        } else {
+            let registers = unsafe { &*cx.registers.get() };
+            let link = Some(registers.link as usize);
+            (
+                Some(registers.sp as usize),
+                walk(link, registers.frame as usize, &bounds),
+            )
+        };
+
+        ThreadDump {
+            id: cx.id,
+            name: cx.name().map(str::to_owned),
+            lifecycle,
+            status: cx.status.get(),
+            running,
+            waiting_on: cx.waiting_on.get(),
+            stack_size: bounds.len(),
+            stack_used: match (lifecycle, sp) {
+                (Lifecycle::New, _) => Some(0),
+                (_, Some(sp)) if bounds.contains(&sp) => Some(top.saturating_sub(sp)),
+                _ => None,
+            },
+            backtrace,
+        }
+    }
+}
+
+impl fmt::Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, thread) in self.threads.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{thread}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ThreadDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "green thread {}", self.id)?;
+        if let Some(name) = &self.name {
+            write!(f, " '{name}'")?;
+        }
+        write!(f, " ({:?}, {:?}", self.lifecycle, self.status)?;
+        if self.running {
+            write!(f, ", running")?;
+        }
+        write!(f, ")")?;
+        if let Some(what) = self.waiting_on {
+            write!(f, ", waiting on {what}")?;
+        }
+        if let Some(used) = self.stack_used {
+            write!(f, ", stack {used}/{} bytes", self.stack_size)?;
+        }
+        writeln!(f)?;
+        for (i, frame) in self.backtrace.iter().enumerate() {
+            writeln!(f, "{i:4}: {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#018x}", self.ip)?;
+        if let Some(symbol) = &self.symbol {
+            write!(f, " - {symbol}+{:#x}", self.offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl Frame {
+    fn resolve(ip: usize) -> Frame {
+        let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+        // The return address points after the call, which may be the start of
+        // the next function.
+        let found = unsafe { libc::dladdr((ip - 1) as *const libc::c_void, &mut info) } != 0;
+        if !found || info.dli_sname.is_null() {
+            return Frame {
+                ip,
+                symbol: None,
+                offset: 0,
+            };
+        }
+        let name = unsafe { CStr::from_ptr(info.dli_sname) }.to_string_lossy();
+        Frame {
+            ip,
+            symbol: Some(demangle(&name)),
+            offset: ip - info.dli_saddr as usize,
+        }
+    }
+}
+
+/// Walks the frame pointer chain starting at `fp`, which must stay within
+/// `bounds` so a corrupted chain is never followed outside of the stack.
+fn walk(link: Option<usize>, mut fp: usize, bounds: &Range<usize>) -> Vec<Frame> {
+    let mut ips = Vec::from_iter(link.filter(|&link| link != 0));
+    while ips.len() < MAX_FRAMES
+        && fp.is_multiple_of(8)
+        && bounds.contains(&fp)
+        && bounds.contains(&(fp + 15))
+    {
+        // A frame record is the caller's frame pointer followed by the
+        // return address.
+        let (next, ip) = unsafe { (*(fp as *const usize), *((fp + 8) as *const usize)) };
+        if ip == 0 {
+            break;
+        }
+        ips.push(ip);
+        if next <= fp {
+            break;
+        }
+        fp = next;
+    }
+    ips.into_iter().map(Frame::resolve).collect()
+}
+
+/// Returns the stack pointer and the frame pointer of the caller.
+#[inline(always)]
+fn frame_registers() -> (usize, usize) {
+    let (sp, fp): (usize, usize);
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        std::arch::asm!("mov {}, sp", "mov {}, x29", out(reg) sp, out(reg) fp)
+    };
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        std::arch::asm!("mov {}, rsp", "mov {}, rbp", out(reg) sp, out(reg) fp)
+    };
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+    {
+        (sp, fp) = (0, 0);
+    }
+    (sp, fp)
+}
+
+/// Demangles a Rust symbol of either mangling scheme, without the hashes,
+/// returning the name unchanged if it isn't one.
+fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+#[test]
+fn dump_lists_threads() {
+    use crate::thread;
+
+    let handle = thread::Builder::new()
+        .name("parked")
+        .spawn(thread::park)
+        .unwrap();
+    thread::yield_now();
+
+    let dump = dump();
+    let parked = dump
+        .threads()
+        .iter()
+        .find(|thread| thread.name.as_deref() == Some("parked"))
+        .unwrap();
+    assert_eq!(parked.lifecycle, Lifecycle::Running);
+    assert_eq!(parked.status, Status::Waiting);
+    assert!(!parked.running);
+    assert!(parked.stack_used.unwrap() > 0);
+    assert!(!parked.backtrace.is_empty());
+
+    let root = dump.threads().iter().find(|thread| thread.running).unwrap();
+    assert_eq!(root.lifecycle, Lifecycle::OsThread);
+
+    handle.thread().unpark();
+    handle.join();
+}
+
+#[test]
+fn demangles_rust_symbols() {
+    assert_eq!(
+        demangle("_ZN6pneuma6thread4park17h0123456789abcdefE"),
+        "pneuma::thread::park"
+    );
+    assert_eq!(
+        demangle("_ZN61_$LT$pneuma..thread..Thread$u20$as$u20$core..clone..Clone$GT$5clone17h0123456789abcdefE"),
+        "<pneuma::thread::Thread as core::clone::Clone>::clone"
+    );
+    assert_eq!(
+        demangle("_RNvNtCs1234_6pneuma6thread4park"),
+        "pneuma::thread::park"
+    );
+    assert_eq!(demangle("main"), "main");
+}
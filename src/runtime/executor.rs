@@ -1,26 +1,55 @@
 use pneuma::thread::Thread;
 
-use pneuma::thread::{Stack};
-use std::cell::{UnsafeCell};
-
-use std::{cell::RefCell, collections::VecDeque};
+use pneuma::thread::Stack;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::rc::Rc;
 
 use crate::sys;
-use crate::thread::context::Status;
+use crate::thread::context::{Lifecycle, Status};
 
+use super::hooks::Hooks;
+use super::preempt::Preemption;
+use super::Scheduler;
 
 pub(crate) struct Executor {
+    /// The context of the OS thread the executor runs on.
+    pub root: Thread,
     pub current: UnsafeCell<Thread>,
-    pub run_queue: RefCell<VecDeque<Thread>>,
+    /// The threads ready to run.
+    pub run_queue: RefCell<Box<dyn Scheduler>>,
+    /// The number of threads that finished while queued, which are still in
+    /// the run queue until they are popped and skipped.
+    finished_in_queue: Cell<usize>,
     pub unused_stacks: RefCell<Vec<Stack>>,
+    pub preemption: Option<Preemption>,
+    /// The number of operations the current thread can perform before it
+    /// has to yield, reset every time a thread is switched in.
+    budget: Cell<u32>,
+    max_budget: Option<u32>,
+    /// The number of context switches performed.
+    pub switches: Cell<u64>,
+    pub hooks: Option<Rc<dyn Hooks>>,
 }
 
 impl Executor {
-    pub fn new() -> Executor {
+    pub fn new(
+        scheduler: Box<dyn Scheduler>,
+        preemption: Option<Preemption>,
+        max_budget: Option<u32>,
+        hooks: Option<Rc<dyn Hooks>>,
+    ) -> Executor {
+        let root = Thread::for_os_thread();
         Executor {
-            current: UnsafeCell::new(Thread::for_os_thread()),
-            run_queue: RefCell::default(),
+            current: UnsafeCell::new(root.clone()),
+            root,
+            run_queue: RefCell::new(scheduler),
+            finished_in_queue: Cell::new(0),
             unused_stacks: RefCell::default(),
+            preemption,
+            budget: Cell::new(max_budget.unwrap_or(0)),
+            max_budget,
+            switches: Cell::new(0),
+            hooks,
         }
     }
 
@@ -31,32 +60,200 @@ impl Executor {
     /// Replaces the current thread with a new coroutine.
     #[inline]
     fn replace(&self, new: Thread) -> Thread {
-        unsafe {
+        if let Some(preemption) = &self.preemption {
+            preemption.start(&new, &self.root);
+        }
+        if let Some(max_budget) = self.max_budget {
+            self.budget.set(max_budget);
+        }
+        let old = unsafe {
             let old = &*self.current.get();
             let old = old.clone();
-            *self.current.get() = new;
+            *self.current.get() = new.clone();
             old
+        };
+        #[cfg(feature = "tracing")]
+        {
+            if let Some(span) = old.0.span.get() {
+                span.with_subscriber(|(id, dispatch)| dispatch.exit(id));
+            }
+            if let Some(span) = new.0.span.get() {
+                span.with_subscriber(|(id, dispatch)| dispatch.enter(id));
+            }
         }
+        old
     }
     #[inline]
     pub fn switch_to(&self, new: Thread) {
         let id = new.id();
         let old = self.replace(new.clone());
+        // A thread may pop itself if it was queued while running.
+        new.status().set(Status::Waiting);
         if id != old.id() {
-            new.status().set(Status::Waiting);
-            unsafe { sys::switch_context(old, new) }
+            self.switches.set(self.switches.get() + 1);
+            if let Some(hooks) = &self.hooks {
+                hooks.on_switch(&old, &new);
+            }
+            #[cfg(feature = "asan")]
+            let fake_stack = sys::asan::start_switch(&new);
+            unsafe { sys::switch_context(old, new) };
+            #[cfg(feature = "asan")]
+            sys::asan::finish_switch(Some(fake_stack));
+        }
+    }
+
+    /// Switches away from the current thread, which has finished, to the next
+    /// thread in the queue, or to the OS thread if there is none.
+    pub fn finish(&self) -> ! {
+        if self.current().status().get() == Status::Queued {
+            self.finished_in_queue.set(self.finished_in_queue.get() + 1);
+        }
+        let next = self.pop().unwrap_or_else(|| self.root.clone());
+        let old = self.replace(next.clone());
+        self.switches.set(self.switches.get() + 1);
+        if let Some(hooks) = &self.hooks {
+            hooks.on_switch(&old, &next);
+        }
+        next.status().set(Status::Waiting);
+        #[cfg(feature = "asan")]
+        unsafe {
+            sys::asan::switch_finished(old, next)
+        };
+        #[cfg(not(feature = "asan"))]
+        unsafe {
+            sys::switch_context(old, next)
+        };
+        unreachable!("resumed a finished thread")
+    }
+
+    /// Consumes one unit of the current thread's budget, returning `false` if
+    /// the budget is exhausted and the thread should yield.
+    pub fn consume_budget(&self) -> bool {
+        if self.max_budget.is_none() {
+            return true;
+        }
+        let budget = self.budget.get();
+        if budget == 0 {
+            return false;
         }
+        self.budget.set(budget - 1);
+        true
     }
 
     pub fn push(&self, thread: Thread) {
-        self.run_queue.borrow_mut().push_back(thread);
+        self.run_queue.borrow_mut().push(thread);
     }
 
     pub fn pop(&self) -> Option<Thread> {
-        self.run_queue.borrow_mut().pop_front()
+        loop {
+            let thread = self.run_queue.borrow_mut().pop()?;
+            // A thread that unparked or cancelled itself may finish while it
+            // is still queued.
+            if let Lifecycle::Finished | Lifecycle::Taken = thread.0.lifecycle.get() {
+                self.finished_in_queue.set(self.finished_in_queue.get() - 1);
+                continue;
+            }
+            return Some(thread);
+        }
     }
 
     pub fn is_empty(&self) -> bool {
         self.run_queue.borrow().is_empty()
     }
+
+    /// Returns the number of green threads in the run queue, leaving out the
+    /// OS thread's own context and the threads that already finished.
+    pub fn queued_threads(&self) -> usize {
+        let root_queued = self.root.status().get() == Status::Queued;
+        self.run_queue.borrow().len() - root_queued as usize - self.finished_in_queue.get()
+    }
+}
+
+#[test]
+fn high_priority_runs_first() {
+    use super::scheduler::Prioritized;
+    use crate::thread::{Builder, Priority};
+    use std::rc::Rc;
+
+    super::Builder::new()
+        .scheduler(Prioritized::default())
+        .install()
+        .unwrap();
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let spawn = |priority| {
+        let order = order.clone();
+        Builder::new()
+            .priority(priority)
+            .spawn(move || order.borrow_mut().push(priority))
+            .unwrap()
+    };
+    let handles = [
+        spawn(Priority::Low),
+        spawn(Priority::Normal),
+        spawn(Priority::High),
+    ];
+    handles.into_iter().for_each(|handle| handle.join());
+    assert_eq!(
+        *order.borrow(),
+        [Priority::High, Priority::Normal, Priority::Low]
+    );
+}
+
+#[test]
+fn low_priority_is_not_starved() {
+    use super::scheduler::Prioritized;
+    use crate::thread::{self, Builder, Priority};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    super::Builder::new()
+        .scheduler(Prioritized::default())
+        .install()
+        .unwrap();
+    let done = Rc::new(Cell::new(false));
+    let busy = (0..2)
+        .map(|_| {
+            let done = done.clone();
+            Builder::new()
+                .priority(Priority::High)
+                .spawn(move || {
+                    while !done.get() {
+                        thread::yield_now();
+                    }
+                })
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let low = Builder::new()
+        .priority(Priority::Low)
+        .spawn(move || done.set(true))
+        .unwrap();
+    low.join();
+    busy.into_iter().for_each(|handle| handle.join());
+}
+
+#[test]
+fn budget_interleaves_busy_threads() {
+    use crate::sync::Mutex;
+    use crate::thread;
+    use std::rc::Rc;
+
+    super::Builder::new().budget(Some(2)).install().unwrap();
+
+    let log = Rc::new(Mutex::new(Vec::new()));
+    let spawn = |name| {
+        let log = log.clone();
+        thread::spawn(move || {
+            for _ in 0..4 {
+                // The lock is never contended, so only the budget makes it yield.
+                log.lock().unwrap().push(name);
+            }
+        })
+    };
+    let handles = [spawn('a'), spawn('b')];
+    handles.into_iter().for_each(|handle| handle.join());
+    assert_eq!(
+        *log.lock().unwrap(),
+        ['a', 'a', 'b', 'b', 'a', 'a', 'b', 'b']
+    );
 }
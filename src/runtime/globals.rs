@@ -1,16 +1,22 @@
-use super::Runtime;
-use std::cell::UnsafeCell;
+use super::{preempt::Preemption, Builder, Runtime};
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::io;
 use std::mem::ManuallyDrop;
 
 thread_local! {
     static RUNTIME: ManuallyDrop<UnsafeCell<Runtime>> =  {
         ON_DROP.with(|_| ());
-        let runtime = Runtime::new();
+        let (builder, preemption) = BUILDER.take().unwrap_or_default();
+        let runtime = Runtime::new(builder, preemption);
+        INITIALIZED.set(true);
         let runtime = UnsafeCell::new(runtime);
         let runtime = ManuallyDrop::new(runtime);
         runtime
     };
     static ON_DROP: OnDrop = const { OnDrop };
+    static INITIALIZED: Cell<bool> = const { Cell::new(false) };
+    /// The configuration the runtime is created with, set by [`install`].
+    static BUILDER: RefCell<Option<(Builder, Option<Preemption>)>> = const { RefCell::new(None) };
 }
 
 pub fn current() -> Runtime {
@@ -21,6 +27,29 @@ pub fn current() -> Runtime {
     })
 }
 
+/// Returns the runtime of the current OS thread, without creating one if it
+/// doesn't exist yet.
+pub(crate) fn try_current() -> Option<Runtime> {
+    if !INITIALIZED.try_with(Cell::get).unwrap_or(false) {
+        return None;
+    }
+    RUNTIME.try_with(|rt| unsafe { &*rt.get() }.clone()).ok()
+}
+
+/// Creates the runtime of the current OS thread with the given configuration.
+pub(crate) fn install(builder: Builder) -> io::Result<()> {
+    if INITIALIZED.get() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "the runtime of this thread has already started",
+        ));
+    }
+    let preemption = Preemption::new(builder.quantum, builder.watchdog)?;
+    BUILDER.set(Some((builder, preemption)));
+    RUNTIME.with(|_| ());
+    Ok(())
+}
+
 struct OnDrop;
 impl Drop for OnDrop {
     fn drop(&mut self) {
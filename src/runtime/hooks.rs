@@ -0,0 +1,135 @@
+use crate::thread::{Thread, ThreadId};
+
+/// Callbacks invoked on the lifecycle events of the green threads of a
+/// runtime, installed with [`Builder::hooks`](super::Builder::hooks).
+///
+/// All the methods do nothing by default, so implementations only need to
+/// override the events they are interested in.
+///
+/// The hooks are called by the scheduler, in the middle of switching threads,
+/// so they must not park, unpark, spawn or join threads, nor use the
+/// primitives of [`pneuma::sync`](crate::sync). They should be cheap, as some
+/// of them are called on every context switch.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::runtime::{self, Hooks};
+/// use pneuma::thread::{self, Thread};
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+///
+/// struct CountSpawns(Rc<Cell<usize>>);
+///
+/// impl Hooks for CountSpawns {
+///     fn on_spawn(&self, _thread: &Thread) {
+///         self.0.set(self.0.get() + 1);
+///     }
+/// }
+///
+/// let spawns = Rc::new(Cell::new(0));
+/// runtime::Builder::new()
+///     .hooks(CountSpawns(spawns.clone()))
+///     .install()
+///     .unwrap();
+///
+/// thread::spawn(|| ()).join();
+/// assert_eq!(spawns.get(), 1);
+/// ```
+pub trait Hooks {
+    /// Called when a thread is spawned, before it is queued to run.
+    fn on_spawn(&self, thread: &Thread) {
+        let _ = thread;
+    }
+
+    /// Called on the thread itself, right before it starts running its
+    /// closure.
+    fn on_first_run(&self, thread: &Thread) {
+        let _ = thread;
+    }
+
+    /// Called when the running thread parks, which includes yielding.
+    fn on_park(&self, thread: &Thread) {
+        let _ = thread;
+    }
+
+    /// Called when a thread is queued to run after being unparked.
+    fn on_unpark(&self, thread: &Thread) {
+        let _ = thread;
+    }
+
+    /// Called when the runtime switches from one thread to another. Either of
+    /// them may be the OS thread's own context.
+    fn on_switch(&self, from: &Thread, to: &Thread) {
+        let _ = (from, to);
+    }
+
+    /// Called on the thread itself once its closure has returned or panicked.
+    fn on_finish(&self, thread: &Thread) {
+        let _ = thread;
+    }
+
+    /// Called when the last handle to a thread is dropped and its resources
+    /// are freed.
+    fn on_drop(&self, id: ThreadId) {
+        let _ = id;
+    }
+}
+
+#[test]
+fn hooks_see_thread_lifecycle() {
+    use crate::thread;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Record(RefCell<Vec<(ThreadId, &'static str)>>);
+
+    impl Hooks for Rc<Record> {
+        fn on_spawn(&self, thread: &Thread) {
+            self.0.borrow_mut().push((thread.id(), "spawn"));
+        }
+        fn on_first_run(&self, thread: &Thread) {
+            self.0.borrow_mut().push((thread.id(), "first run"));
+        }
+        fn on_unpark(&self, thread: &Thread) {
+            self.0.borrow_mut().push((thread.id(), "unpark"));
+        }
+        fn on_switch(&self, from: &Thread, to: &Thread) {
+            self.0.borrow_mut().push((from.id(), "switch from"));
+            self.0.borrow_mut().push((to.id(), "switch to"));
+        }
+        fn on_finish(&self, thread: &Thread) {
+            self.0.borrow_mut().push((thread.id(), "finish"));
+        }
+    }
+
+    let record = Rc::new(Record::default());
+    super::Builder::new()
+        .hooks(record.clone())
+        .install()
+        .unwrap();
+
+    let handle = thread::spawn(|| ());
+    let id = handle.thread().id();
+    handle.join();
+
+    let events: Vec<_> = record
+        .0
+        .borrow()
+        .iter()
+        .filter(|(thread, _)| *thread == id)
+        .map(|(_, event)| *event)
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "spawn",
+            "unpark",
+            "switch to",
+            "first run",
+            "finish",
+            "switch from"
+        ]
+    );
+}
@@ -0,0 +1,91 @@
+use super::current;
+
+/// A snapshot of the metrics of a runtime, returned by [`metrics`].
+///
+/// The counters are totals since the runtime started, the other fields
+/// describe the runtime at the time of the snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Metrics {
+    /// The number of spawned green threads that haven't finished yet.
+    pub live_threads: usize,
+    /// The number of live threads that are running or queued to run.
+    pub runnable_threads: usize,
+    /// The number of live threads that are parked, waiting to be unparked.
+    pub parked_threads: usize,
+    /// The number of green threads in the run queue.
+    pub queue_depth: usize,
+    /// The total number of context switches between green threads, including
+    /// the OS thread's own context.
+    pub context_switches: u64,
+    /// The total number of events that woke up threads, which are timers
+    /// firing and wake ups from other OS threads.
+    pub events: u64,
+    /// The size of all the stacks currently mapped, in bytes.
+    pub bytes_mapped: usize,
+}
+
+/// Takes a snapshot of the metrics of the runtime of the current OS thread.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// let handle = thread::spawn(thread::park);
+/// thread::yield_now();
+///
+/// let metrics = pneuma::runtime::metrics();
+/// assert_eq!(metrics.live_threads, 1);
+/// assert_eq!(metrics.parked_threads, 1);
+///
+/// handle.thread().unpark();
+/// handle.join();
+/// ```
+pub fn metrics() -> Metrics {
+    current().metrics()
+}
+
+#[test]
+fn metrics_track_threads() {
+    use crate::thread;
+
+    let before = metrics();
+    let parked = thread::spawn(thread::park);
+    let queued = thread::spawn(|| ());
+
+    let metrics = metrics();
+    assert_eq!(metrics.live_threads, 2);
+    assert_eq!(metrics.runnable_threads, 2);
+    assert_eq!(metrics.queue_depth, 2);
+    assert!(metrics.bytes_mapped >= before.bytes_mapped + 2 * (1 << 14));
+
+    queued.join();
+    let metrics = super::metrics();
+    assert_eq!(metrics.live_threads, 1);
+    assert_eq!(metrics.parked_threads, 1);
+    assert_eq!(metrics.queue_depth, 0);
+    assert!(metrics.context_switches > before.context_switches);
+
+    parked.thread().unpark();
+    parked.join();
+    assert_eq!(super::metrics().live_threads, 0);
+}
+
+#[test]
+fn metrics_leave_out_the_os_thread() {
+    use crate::thread;
+
+    let parked = thread::spawn(thread::park);
+    let running = thread::spawn(metrics);
+    // the OS thread's context is queued while the threads run
+    thread::yield_now();
+
+    let metrics = running.join();
+    assert_eq!(metrics.runnable_threads, 1);
+    assert_eq!(metrics.parked_threads, 1);
+    assert_eq!(metrics.queue_depth, 0);
+
+    parked.thread().unpark();
+    parked.join();
+}
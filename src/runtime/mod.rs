@@ -1,13 +1,98 @@
-use pneuma::thread::park;
-use std::cell::Cell;
-use std::{rc::Rc};
+//! The pneuma runtime.
+//!
+//! Every OS thread that runs green threads has its own runtime, which is created
+//! the first time it is used. This module exposes the knobs to configure it,
+//! either before it starts with a [`Builder`], or while it runs.
+
+use pneuma::thread::context::Lifecycle;
+use pneuma::thread::{self, park};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 // use pneuma::reactor::Reactor;
 // use pneuma::thread::JoinHandle;
+pub use builder::Builder;
+pub use deadlock::DeadlockPolicy;
+pub use dump::{dump, dump_on_sigusr1, Dump, Frame, ThreadDump};
 use executor::Executor;
-pub use globals::current;
+pub(crate) use globals::{current, try_current};
+pub use hooks::Hooks;
+pub use metrics::{metrics, Metrics};
+pub(crate) use panic::PanicHandler;
+pub(crate) use remote::Remote;
+pub use scheduler::Scheduler;
+pub(crate) use timers::Timers;
+mod builder;
 // mod config;
+mod deadlock;
+mod dump;
 mod executor;
 mod globals;
+mod hooks;
+mod metrics;
+pub(crate) mod panic;
+pub(crate) mod preempt;
+mod remote;
+pub mod scheduler;
+mod timers;
+
+/// Sets a callback invoked whenever a green thread on the current OS thread
+/// panics, replacing the previous one.
+///
+/// The callback receives the thread that panicked and the panic payload. It is
+/// called before the thread finishes, so it also sees the panics of detached
+/// threads, which would otherwise go unnoticed. It can be used to record the
+/// panics, or to escalate them, for example by aborting the process.
+///
+/// Threads unwinding because they were cancelled with
+/// [`Cancel::Unwind`](crate::thread::Cancel::Unwind) are not reported.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// pneuma::runtime::on_thread_panic(|thread, _payload| {
+///     eprintln!("thread {:?} panicked", thread.name());
+/// });
+///
+/// thread::spawn(|| panic!("oops"));
+/// thread::yield_now();
+/// ```
+pub fn on_thread_panic<F>(f: F)
+where
+    F: Fn(&thread::Thread, &(dyn Any + Send)) + 'static,
+{
+    *current().panic_handler.borrow_mut() = Some(Rc::new(f));
+}
+
+/// Sets the maximum number of green threads that can be alive at the same time
+/// on the current OS thread, or removes the limit if `max` is `None`.
+///
+/// Once the limit is reached, [`Builder::spawn`](crate::thread::Builder::spawn)
+/// returns a [`WouldBlock`](std::io::ErrorKind::WouldBlock) error until one of
+/// the threads finishes. Lowering the limit doesn't affect the threads that are
+/// already running. There is no limit by default.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+/// use std::io;
+///
+/// pneuma::runtime::set_max_threads(Some(1));
+///
+/// let handle = thread::spawn(|| ());
+/// let err = thread::Builder::new().spawn(|| ()).unwrap_err();
+/// assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+/// handle.join();
+/// ```
+pub fn set_max_threads(max: Option<usize>) {
+    current().max_threads.set(max);
+}
 
 #[derive(Clone)]
 pub(crate) struct Runtime(Rc<InnerRuntime>);
@@ -15,30 +100,105 @@ pub(crate) struct Runtime(Rc<InnerRuntime>);
 pub(crate) struct InnerRuntime {
     shutdown: Cell<bool>,
     polls: Cell<usize>,
+    /// The number of timers fired and wake ups received from other OS threads.
+    events: Cell<u64>,
+    /// The number of spawned threads that haven't finished yet.
+    threads: Cell<usize>,
+    max_threads: Cell<Option<usize>>,
     pub executor: Executor,
+    pub timers: Timers,
+    pub remote: Arc<Remote>,
+    pub panic_handler: RefCell<Option<PanicHandler>>,
+    /// Whether the panic hook set before the runtime started is called for
+    /// green threads.
+    pub chain_panic_hook: bool,
+    deadlock_policy: DeadlockPolicy,
+    /// Whether the current deadlock was logged, reset when a thread runs.
+    deadlock_reported: Cell<bool>,
     // reactor: Reactor,
 }
 
 impl Runtime {
-    pub(crate) fn new() -> Self {
-        let executor = Executor::new();
+    pub(crate) fn new(builder: Builder, preemption: Option<preempt::Preemption>) -> Self {
+        panic::install_hook();
+        let executor = Executor::new(builder.scheduler, preemption, builder.budget, builder.hooks);
+        let remote = Remote::new();
+        dump::register(&remote);
         let shutdown = Cell::new(false);
         let polls = Cell::new(0);
         Runtime(Rc::new(InnerRuntime {
             executor,
+            timers: Timers::default(),
+            remote,
+            panic_handler: RefCell::new(None),
+            chain_panic_hook: builder.chain_panic_hook,
+            deadlock_policy: builder.deadlock_policy,
+            deadlock_reported: Cell::new(false),
             shutdown,
             polls,
+            events: Cell::new(0),
+            threads: Cell::new(0),
+            max_threads: Cell::new(builder.max_threads),
         }))
     }
 
     pub(crate) fn shutdown(self) {
         self.shutdown.set(true);
+        if let Some(preemption) = &self.executor.preemption {
+            preemption.stop();
+        }
 
         while !self.executor.is_empty() {
             park()
         }
     }
 
+    /// Takes a snapshot of the runtime's metrics.
+    pub(crate) fn metrics(&self) -> Metrics {
+        let live_threads = self.threads.get();
+        let queue_depth = self.executor.queued_threads();
+        let current = self.executor.current();
+        let running = current.id() != self.executor.root.id()
+            && !matches!(
+                current.0.lifecycle.get(),
+                Lifecycle::Finished | Lifecycle::Taken
+            );
+        let runnable_threads = queue_depth + running as usize;
+        Metrics {
+            live_threads,
+            runnable_threads,
+            parked_threads: live_threads - runnable_threads,
+            queue_depth,
+            context_switches: self.executor.switches.get(),
+            events: self.events.get(),
+            bytes_mapped: crate::thread::stack::mapped_bytes(),
+        }
+    }
+
+    /// Returns whether threads are queued to run, or could be woken up by a
+    /// timer or from another OS thread.
+    pub(crate) fn has_pending_work(&self) -> bool {
+        !self.executor.is_empty() || !self.timers.is_empty() || self.remote.has_wakers()
+    }
+
+    /// Accounts for a new thread, failing if the thread limit is reached.
+    pub(crate) fn reserve_thread(&self) -> io::Result<()> {
+        let threads = self.threads.get();
+        if self.max_threads.get().is_some_and(|max| threads >= max) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "the maximum number of threads has been reached",
+            ));
+        }
+        self.threads.set(threads + 1);
+        Ok(())
+    }
+
+    /// Releases a thread reserved with [`reserve_thread`](Self::reserve_thread).
+    pub(crate) fn release_thread(&self) {
+        self.threads.set(self.threads.get() - 1);
+    }
+
     // /// Switches to the next
     // pub fn switch(&self) -> RcContext {
     //     let polls = (self.polls.get() + 1) % 61;
@@ -76,12 +236,69 @@ impl Runtime {
         }
     }
 
+    /// Fires the expired timers and unparks the threads woken up from other
+    /// OS threads.
+    ///
+    /// If `wait` is set and there are no threads ready to run, the OS thread
+    /// sleeps until the next timer expires or until it is woken up by another
+    /// OS thread. If there is nothing that could wake up a thread, this returns
+    /// immediately.
+    pub fn poll_events(&self, wait: bool) {
+        if self.remote.take_dump_request() {
+            eprintln!("{}", self.dump());
+        }
+        if wait && self.executor.is_empty() {
+            if let Some(preemption) = &self.executor.preemption {
+                preemption.pause();
+            }
+            let now = Instant::now();
+            match self.timers.next_deadline() {
+                Some(deadline) => {
+                    std::thread::park_timeout(deadline.saturating_duration_since(now))
+                }
+                None if self.remote.has_wakers() => std::thread::park(),
+                None => (),
+            }
+            if let Some(preemption) = &self.executor.preemption {
+                preemption.start(&self.executor.current(), &self.executor.root);
+            }
+        }
+        let mut events = self.remote.drain();
+        if !self.timers.is_empty() {
+            events += self.timers.fire(Instant::now());
+        }
+        self.events.set(self.events.get() + events as u64);
+    }
+
     pub fn park(&self) {
+        if let Some(hooks) = &self.executor.hooks {
+            hooks.on_park(&self.executor.current());
+        }
         self.poll();
+        self.poll_events(false);
         if let Some(next) = self.executor.pop() {
+            self.deadlock_reported.set(false);
             return self.executor.switch_to(next);
         }
         self.poll_reactor();
+        self.poll_events(true);
+        if let Some(next) = self.executor.pop() {
+            self.deadlock_reported.set(false);
+            return self.executor.switch_to(next);
+        }
+        if self.is_deadlocked() {
+            self.deadlock();
+        }
+    }
+
+    /// Parks the current thread until it is unparked or until `dur` elapses.
+    pub fn park_timeout(&self, dur: Duration) {
+        let Some(deadline) = Instant::now().checked_add(dur) else {
+            return self.park();
+        };
+        let key = self.timers.insert(deadline, thread::current());
+        self.park();
+        self.timers.remove(key);
     }
 }
 
@@ -0,0 +1,192 @@
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::io::Write;
+use std::panic::{self, Location, PanicHookInfo};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Once, OnceLock};
+
+use pneuma::thread::{Cancel, Thread};
+
+use crate::sys;
+
+use super::try_current;
+
+/// Callback invoked when a green thread panics.
+pub(crate) type PanicHandler = Rc<dyn Fn(&Thread, &(dyn Any + Send))>;
+
+/// Installs a panic hook that reports the name of the green thread that
+/// panicked, instead of the name of the OS thread. Panics outside of green
+/// threads are forwarded to the previous hook.
+///
+/// On green threads the report replaces std's default message. The previous
+/// hook is only called after it if the runtime was built with
+/// [`chain_panic_hook`](super::Builder::chain_panic_hook), as std can't tell
+/// a hook set by the user apart from its default one, which would print the
+/// panic a second time. Both run on the OS thread's stack, as capturing a
+/// backtrace takes more than the small stacks of green threads.
+pub(crate) fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| match current_green_thread() {
+            Some((thread, top)) => unsafe {
+                let chain = try_current().is_some_and(|rt| rt.chain_panic_hook);
+                sys::on_stack(top, || {
+                    report(&thread, info);
+                    if chain {
+                        previous(info);
+                    }
+                })
+            },
+            None => previous(info),
+        }));
+    });
+}
+
+/// Returns the current green thread, if the OS thread is running one, along
+/// with the top of the free part of the OS thread's stack.
+fn current_green_thread() -> Option<(Thread, usize)> {
+    let rt = try_current()?;
+    let thread = rt.executor.current();
+    if thread.id() == rt.executor.root.id() {
+        return None;
+    }
+    // The root context is suspended, so nothing lives below its stack
+    // pointer. The margin leaves room for a red zone.
+    let sp = unsafe { (*rt.executor.root.0.registers.get()).sp } as usize;
+    Some((thread, (sp - 256) & !15))
+}
+
+/// How backtraces are printed, following std's `RUST_BACKTRACE` semantics.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum BacktraceStyle {
+    Off,
+    Short,
+    Full,
+}
+
+impl BacktraceStyle {
+    fn from_env() -> BacktraceStyle {
+        static STYLE: OnceLock<BacktraceStyle> = OnceLock::new();
+        *STYLE.get_or_init(|| match std::env::var_os("RUST_BACKTRACE") {
+            Some(style) if style == "full" => BacktraceStyle::Full,
+            Some(style) if style == "0" => BacktraceStyle::Off,
+            Some(_) => BacktraceStyle::Short,
+            None => BacktraceStyle::Off,
+        })
+    }
+}
+
+fn report(thread: &Thread, info: &PanicHookInfo<'_>) {
+    // Like std, the hint to enable backtraces is only given once.
+    static FIRST_PANIC: AtomicBool = AtomicBool::new(true);
+
+    let message = message(thread, info.payload_as_str(), info.location());
+    let mut stderr = std::io::stderr().lock();
+    let _ = writeln!(stderr, "{message}");
+    match BacktraceStyle::from_env() {
+        BacktraceStyle::Off => {
+            if FIRST_PANIC.swap(false, Ordering::Relaxed) {
+                let _ = writeln!(
+                    stderr,
+                    "note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace"
+                );
+            }
+        }
+        BacktraceStyle::Short => {
+            let _ = writeln!(stderr, "stack backtrace:\n{}", Backtrace::force_capture());
+        }
+        BacktraceStyle::Full => {
+            let _ = writeln!(stderr, "stack backtrace:\n{:#}", Backtrace::force_capture());
+        }
+    }
+}
+
+/// Formats the first line of the report of a panic in `thread`.
+fn message(thread: &Thread, msg: Option<&str>, location: Option<&Location<'_>>) -> String {
+    let name = thread.name().unwrap_or("<unnamed>");
+    let msg = msg.unwrap_or("Box<dyn Any>");
+    let location = location.map(Location::to_string).unwrap_or_default();
+    format!("green thread '{name}' panicked at {location}:\n{msg}")
+}
+
+/// Invokes the runtime's panic handler, if any, for a thread that finished
+/// with a panic. Unwinding caused by cancellation is not reported.
+pub(crate) fn on_thread_panic(thread: &Thread, payload: &(dyn Any + Send)) {
+    if payload.downcast_ref::<Cancel>().is_some() {
+        return;
+    }
+    let Some(rt) = try_current() else {
+        return;
+    };
+    let handler = rt.panic_handler.borrow().clone();
+    if let Some(handler) = handler {
+        handler(thread, payload);
+    }
+}
+
+#[test]
+fn message_names_the_green_thread() {
+    let handle = pneuma::thread::Builder::new()
+        .name("worker")
+        .spawn(|| ())
+        .unwrap();
+    let location = Location::caller();
+    assert_eq!(
+        message(handle.thread(), Some("boom"), Some(location)),
+        format!("green thread 'worker' panicked at {location}:\nboom")
+    );
+    handle.join();
+}
+
+#[test]
+fn backtraces_are_captured_on_the_os_stack() {
+    // Symbolising overflows the default stack of green threads.
+    let backtrace = pneuma::thread::spawn(|| {
+        let (_, top) = current_green_thread().unwrap();
+        let mut backtrace = String::new();
+        unsafe {
+            sys::on_stack(top, || {
+                backtrace = format!("{:#}", Backtrace::force_capture())
+            })
+        };
+        backtrace
+    })
+    .join();
+    // The unwinder crosses back to the green thread's frames.
+    assert!(backtrace.contains("call_on_stack"), "{backtrace}");
+    assert!(backtrace.contains("__green_thread_start"), "{backtrace}");
+}
+
+#[test]
+fn on_thread_panic_sees_panics_but_not_cancellation() {
+    use pneuma::thread;
+    use std::cell::RefCell;
+
+    let panics = Rc::new(RefCell::new(vec![]));
+    let seen = panics.clone();
+    super::on_thread_panic(move |thread, payload| {
+        let msg = payload.downcast_ref::<&str>().copied();
+        seen.borrow_mut()
+            .push((thread.name().map(|name| name.to_string()), msg));
+    });
+
+    let panicking = thread::Builder::new()
+        .name("panicking")
+        .spawn(|| panic!("oops"))
+        .unwrap();
+    assert!(panicking.try_join().is_err());
+
+    let cancelled = thread::spawn(|| loop {
+        thread::park();
+    });
+    thread::yield_now();
+    cancelled.cancel(Cancel::Unwind);
+    assert!(cancelled.try_join().is_err());
+
+    assert_eq!(
+        *panics.borrow(),
+        [(Some("panicking".to_owned()), Some("oops"))]
+    );
+}
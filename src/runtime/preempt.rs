@@ -0,0 +1,250 @@
+//! Time slice preemption and the watchdog.
+//!
+//! Preemption is cooperative: a per-OS-thread CPU time timer delivers
+//! `SIGVTALRM` every quantum, and the signal handler only sets a flag. The
+//! running green thread yields the next time it reaches a safe point, which is
+//! a call to [`checkpoint`](crate::thread::checkpoint).
+//!
+//! The watchdog is an OS thread shared by all the runtimes that enable it. It
+//! logs the green threads that have been running for longer than the limit
+//! without yielding, which also catches the threads that never reach a
+//! checkpoint.
+
+use std::cell::Cell;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once, OnceLock, Weak};
+use std::time::{Duration, Instant};
+
+use crate::thread::Thread;
+
+/// How often the watchdog checks the running threads.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(10);
+
+thread_local! {
+    /// Set by the signal handler when the quantum of the current thread expired.
+    static PENDING: AtomicBool = const { AtomicBool::new(false) };
+}
+
+/// Returns whether the current thread should yield, clearing the request.
+pub(crate) fn take_pending() -> bool {
+    PENDING
+        .try_with(|pending| pending.swap(false, Ordering::Relaxed))
+        .unwrap_or(false)
+}
+
+/// The preemption state of a runtime.
+pub(crate) struct Preemption {
+    timer: Cell<Option<Timer>>,
+    slice: Option<Arc<Slice>>,
+}
+
+impl Preemption {
+    /// Starts the preemption timer if `quantum` is set, and registers the
+    /// runtime with the watchdog if `limit` is set.
+    pub fn new(quantum: Option<Duration>, limit: Option<Duration>) -> io::Result<Option<Self>> {
+        if quantum.is_none() && limit.is_none() {
+            return Ok(None);
+        }
+        let timer = quantum.map(Timer::new).transpose()?;
+        let slice = limit.map(Slice::register);
+        Ok(Some(Preemption {
+            timer: Cell::new(timer),
+            slice,
+        }))
+    }
+
+    /// Starts the time slice of `thread`, which is about to run.
+    pub fn start(&self, thread: &Thread, root: &Thread) {
+        PENDING.with(|pending| pending.store(false, Ordering::Relaxed));
+        if let Some(slice) = &self.slice {
+            // The OS thread's own context isn't a green thread, so it isn't watched.
+            let id = if thread.id() == root.id() {
+                0
+            } else {
+                thread.id().as_u64()
+            };
+            slice.start(id);
+        }
+    }
+
+    /// Stops watching the current thread while the OS thread sleeps.
+    pub fn pause(&self) {
+        if let Some(slice) = &self.slice {
+            slice.start(0);
+        }
+    }
+
+    /// Stops the preemption timer.
+    pub fn stop(&self) {
+        self.timer.take();
+    }
+}
+
+/// A CPU time timer that sends `SIGVTALRM` to the OS thread that created it.
+struct Timer(libc::timer_t);
+
+impl Timer {
+    #[cfg(target_os = "linux")]
+    fn new(quantum: Duration) -> io::Result<Timer> {
+        static HANDLER: Once = Once::new();
+        HANDLER.call_once(|| unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as usize;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGVTALRM, &action, std::ptr::null_mut());
+        });
+
+        unsafe {
+            let mut event: libc::sigevent = std::mem::zeroed();
+            event.sigev_notify = libc::SIGEV_THREAD_ID;
+            event.sigev_signo = libc::SIGVTALRM;
+            event.sigev_notify_thread_id = libc::gettid();
+            let mut timer = std::mem::zeroed();
+            if libc::timer_create(libc::CLOCK_THREAD_CPUTIME_ID, &mut event, &mut timer) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let timer = Timer(timer);
+
+            let quantum = libc::timespec {
+                tv_sec: quantum.as_secs() as _,
+                tv_nsec: quantum.subsec_nanos().max(1) as _,
+            };
+            let spec = libc::itimerspec {
+                it_interval: quantum,
+                it_value: quantum,
+            };
+            if libc::timer_settime(timer.0, 0, &spec, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(timer)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn new(_quantum: Duration) -> io::Result<Timer> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "preemption is not supported on this platform",
+        ))
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        unsafe { libc::timer_delete(self.0) };
+    }
+}
+
+extern "C" fn on_signal(_: libc::c_int) {
+    let _ = PENDING.try_with(|pending| pending.store(true, Ordering::Relaxed));
+}
+
+/// The time slice of the thread running on an OS thread, as seen by the
+/// watchdog.
+struct Slice {
+    /// The id of the running green thread, or 0 if none is being watched.
+    thread: AtomicU64,
+    /// When the slice started, in nanoseconds since [`epoch`].
+    started: AtomicU64,
+    /// Whether the current slice has already been reported.
+    reported: AtomicBool,
+    limit: Duration,
+    os_thread: String,
+}
+
+impl Slice {
+    fn register(limit: Duration) -> Arc<Slice> {
+        let os_thread = std::thread::current();
+        let slice = Arc::new(Slice {
+            thread: AtomicU64::new(0),
+            started: AtomicU64::new(0),
+            reported: AtomicBool::new(false),
+            limit,
+            os_thread: os_thread.name().unwrap_or("<unnamed>").to_owned(),
+        });
+        watchdog().lock().unwrap().push(Arc::downgrade(&slice));
+        slice
+    }
+
+    fn start(&self, thread: u64) {
+        self.thread.store(0, Ordering::Relaxed);
+        self.started
+            .store(epoch().elapsed().as_nanos() as u64, Ordering::Relaxed);
+        self.reported.store(false, Ordering::Relaxed);
+        self.thread.store(thread, Ordering::Release);
+    }
+
+    /// Logs the running thread if it exceeded its slice.
+    fn check(&self, now: Duration) {
+        let thread = self.thread.load(Ordering::Acquire);
+        if thread == 0 || self.reported.load(Ordering::Relaxed) {
+            return;
+        }
+        let started = Duration::from_nanos(self.started.load(Ordering::Relaxed));
+        let running = now.saturating_sub(started);
+        if running > self.limit && !self.reported.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "pneuma: green thread {thread} on OS thread '{}' has been running for {running:?} without yielding",
+                self.os_thread,
+            );
+        }
+    }
+}
+
+fn epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+/// Returns the slices watched by the watchdog, starting it if needed.
+fn watchdog() -> &'static Mutex<Vec<Weak<Slice>>> {
+    static SLICES: Mutex<Vec<Weak<Slice>>> = Mutex::new(Vec::new());
+    static WATCHDOG: Once = Once::new();
+    WATCHDOG.call_once(|| {
+        std::thread::Builder::new()
+            .name("pneuma-watchdog".into())
+            .spawn(|| loop {
+                std::thread::sleep(WATCHDOG_INTERVAL);
+                let now = epoch().elapsed();
+                SLICES
+                    .lock()
+                    .unwrap()
+                    .retain(|slice| match slice.upgrade() {
+                        Some(slice) => {
+                            slice.check(now);
+                            true
+                        }
+                        None => false,
+                    });
+            })
+            .expect("failed to spawn the pneuma watchdog");
+    });
+    &SLICES
+}
+
+#[test]
+fn checkpoint_preempts_busy_threads() {
+    use crate::thread;
+    use std::rc::Rc;
+
+    super::Builder::new()
+        .preemption(Some(Duration::from_millis(1)))
+        .install()
+        .unwrap();
+
+    let done = Rc::new(Cell::new(false));
+    let busy = {
+        let done = done.clone();
+        thread::spawn(move || {
+            while !done.get() {
+                thread::checkpoint();
+            }
+        })
+    };
+    // Only runs if the busy thread is preempted.
+    let other = thread::spawn(move || done.set(true));
+    other.join();
+    busy.join();
+}
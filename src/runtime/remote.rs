@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use pneuma::future::ThreadWaker;
+
+/// Wake ups sent to this runtime from other OS threads.
+///
+/// Green threads can only be unparked from the OS thread they run on, so
+/// wakers used from other OS threads are queued here, and the runtime unparks
+/// their threads the next time it parks.
+pub(crate) struct Remote {
+    woken: Mutex<Vec<Arc<ThreadWaker>>>,
+    has_woken: AtomicBool,
+    /// The number of wakers that may still wake up a thread.
+    wakers: AtomicUsize,
+    /// Set when a dump of the runtime was requested with `SIGUSR1`.
+    dump_requested: AtomicBool,
+    os_thread: std::thread::Thread,
+}
+
+impl Remote {
+    pub fn new() -> Arc<Remote> {
+        Arc::new(Remote {
+            woken: Mutex::default(),
+            has_woken: AtomicBool::new(false),
+            wakers: AtomicUsize::new(0),
+            dump_requested: AtomicBool::new(false),
+            os_thread: std::thread::current(),
+        })
+    }
+
+    /// Queues a waker to be woken up on the runtime's OS thread, waking
+    /// the OS thread up if it is sleeping.
+    pub fn push(&self, waker: Arc<ThreadWaker>) {
+        self.woken.lock().unwrap().push(waker);
+        self.has_woken.store(true, Ordering::Release);
+        self.os_thread.unpark();
+    }
+
+    /// Unparks the threads woken up from other OS threads, returning how many
+    /// wake ups were received. This must only be called from the runtime's OS
+    /// thread.
+    pub fn drain(&self) -> usize {
+        if !self.has_woken.swap(false, Ordering::Acquire) {
+            return 0;
+        }
+        let woken = std::mem::take(&mut *self.woken.lock().unwrap());
+        for waker in &woken {
+            waker.unpark();
+        }
+        woken.len()
+    }
+
+    /// Asks the runtime to print a dump, waking the OS thread up if it is
+    /// sleeping.
+    pub fn request_dump(&self) {
+        self.dump_requested.store(true, Ordering::Relaxed);
+        self.os_thread.unpark();
+    }
+
+    /// Returns whether a dump was requested, clearing the request.
+    pub fn take_dump_request(&self) -> bool {
+        self.dump_requested.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn register_waker(&self) {
+        self.wakers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn deregister_waker(&self) {
+        self.wakers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Whether a thread may be woken up from another OS thread.
+    pub fn has_wakers(&self) -> bool {
+        self.wakers.load(Ordering::Relaxed) != 0
+    }
+}
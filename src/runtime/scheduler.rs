@@ -0,0 +1,216 @@
+//! Scheduling policies for the run queue.
+//!
+//! The executor keeps the threads that are ready to run in a [`Scheduler`],
+//! which decides the order in which they run. A custom scheduler can be
+//! installed with [`Builder::scheduler`](super::Builder::scheduler).
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::thread::{self, Priority, Thread};
+
+/// The number of threads popped from the queues after which a waiting thread
+/// is served as if it had one more priority level.
+const AGING_INTERVAL: u64 = 16;
+
+/// Decides the order in which the runnable threads of a runtime run.
+///
+/// The executor pushes a thread when it is unparked and pops the next thread
+/// to run whenever the current thread parks. A thread is never pushed again
+/// before it has been popped.
+///
+/// The scheduler is called while the executor is switching threads, so it
+/// must not park, spawn threads, or unpark threads itself.
+///
+/// Implementations should be checked with [`conformance`].
+///
+/// # Examples
+///
+/// A scheduler that runs the most recently unparked thread first:
+///
+/// ```
+/// use pneuma::runtime::{self, Scheduler};
+/// use pneuma::thread::Thread;
+///
+/// #[derive(Default)]
+/// struct Lifo(Vec<Thread>);
+///
+/// impl Scheduler for Lifo {
+///     fn push(&mut self, thread: Thread) {
+///         self.0.push(thread);
+///     }
+///
+///     fn pop(&mut self) -> Option<Thread> {
+///         self.0.pop()
+///     }
+///
+///     fn len(&self) -> usize {
+///         self.0.len()
+///     }
+/// }
+///
+/// runtime::scheduler::conformance(Lifo::default);
+/// ```
+pub trait Scheduler {
+    /// Adds a thread that is ready to run.
+    fn push(&mut self, thread: Thread);
+
+    /// Removes the next thread to run, if any.
+    fn pop(&mut self) -> Option<Thread>;
+
+    /// Returns the number of threads waiting to run.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if no thread is waiting to run.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Runs the threads in the order they were unparked, ignoring their priority.
+///
+/// This is the default scheduler.
+#[derive(Default)]
+pub struct Fifo(VecDeque<Thread>);
+
+impl Scheduler for Fifo {
+    fn push(&mut self, thread: Thread) {
+        self.0.push_back(thread);
+    }
+
+    fn pop(&mut self) -> Option<Thread> {
+        self.0.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Runs the threads with the highest [`Priority`] first, and the threads with
+/// the same priority in the order they were unparked.
+///
+/// Threads that have been waiting for a while are served as if they had a
+/// higher priority, so busy high priority threads can't starve the others.
+#[derive(Default)]
+pub struct Prioritized {
+    /// One queue per priority level, holding the threads along with the value
+    /// of `pops` when they were queued.
+    queues: [VecDeque<(Thread, u64)>; Priority::LEVELS],
+    /// The number of threads popped from the queues so far.
+    pops: u64,
+}
+
+impl Scheduler for Prioritized {
+    fn push(&mut self, thread: Thread) {
+        let level = thread.priority().level();
+        self.queues[level].push_back((thread, self.pops));
+    }
+
+    /// Pops the thread at the front of the highest priority queue, taking into
+    /// account how long the threads at the front of each queue have waited.
+    fn pop(&mut self) -> Option<Thread> {
+        let mut next: Option<(usize, u64)> = None;
+        for (level, queue) in self.queues.iter().enumerate().rev() {
+            let Some((_, queued_at)) = queue.front() else {
+                continue;
+            };
+            let effective = level as u64 + (self.pops - queued_at) / AGING_INTERVAL;
+            if next.is_none_or(|(_, best)| effective > best) {
+                next = Some((level, effective));
+            }
+        }
+        let (level, _) = next?;
+        self.pops += 1;
+        self.queues[level].pop_front().map(|(thread, _)| thread)
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+}
+
+/// Checks that the schedulers created by `new` behave like the executor
+/// expects, panicking otherwise.
+///
+/// Every thread pushed must be popped exactly once, `len` must track the
+/// number of queued threads, and a scheduler must not lose threads when
+/// pushes and pops are interleaved. The order in which the threads are
+/// popped is up to the scheduler.
+///
+/// The threads used by the checks are never run.
+pub fn conformance<S, F>(new: F)
+where
+    S: Scheduler,
+    F: Fn() -> S,
+{
+    let threads = (0..64)
+        .map(|i| {
+            let priority = [Priority::Low, Priority::Normal, Priority::High][i % Priority::LEVELS];
+            let builder = thread::Builder::new().stack_size(0).priority(priority);
+            Thread(thread::RcContext::new(|| (), builder).unwrap())
+        })
+        .collect::<Vec<_>>();
+
+    let mut scheduler = new();
+    assert!(scheduler.is_empty(), "a new scheduler must be empty");
+    assert!(
+        scheduler.pop().is_none(),
+        "an empty scheduler must not pop a thread"
+    );
+
+    // A single thread comes back.
+    scheduler.push(threads[0].clone());
+    assert_eq!(scheduler.len(), 1);
+    let popped = scheduler.pop().expect("the pushed thread must be popped");
+    assert_eq!(popped.id(), threads[0].id());
+    assert!(scheduler.is_empty());
+
+    // Every thread is popped exactly once.
+    for (i, thread) in threads.iter().enumerate() {
+        scheduler.push(thread.clone());
+        assert_eq!(scheduler.len(), i + 1, "len must count the pushed threads");
+    }
+    let mut seen = HashSet::new();
+    while let Some(thread) = scheduler.pop() {
+        assert!(
+            seen.insert(thread.id()),
+            "thread {} was popped twice",
+            thread.id()
+        );
+    }
+    assert_eq!(seen.len(), threads.len(), "threads were lost");
+    assert!(scheduler.is_empty());
+
+    // Interleaved pushes and pops, like threads that keep yielding.
+    let mut scheduler = new();
+    let mut queued = HashSet::new();
+    for thread in &threads {
+        scheduler.push(thread.clone());
+        queued.insert(thread.id());
+        if thread.id().as_u64() % 3 == 0 {
+            let popped = scheduler.pop().expect("a thread must be popped");
+            assert!(
+                queued.remove(&popped.id()),
+                "popped a thread that wasn't queued"
+            );
+        }
+    }
+    assert_eq!(scheduler.len(), queued.len());
+    while let Some(thread) = scheduler.pop() {
+        assert!(
+            queued.remove(&thread.id()),
+            "popped a thread that wasn't queued"
+        );
+    }
+    assert!(queued.is_empty(), "threads were lost");
+}
+
+#[test]
+fn fifo_conforms() {
+    conformance(Fifo::default);
+}
+
+#[test]
+fn prioritized_conforms() {
+    conformance(Prioritized::default);
+}
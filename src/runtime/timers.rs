@@ -0,0 +1,61 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use pneuma::thread::Thread;
+
+/// Identifies a timer registered on the runtime, used to cancel it.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub(crate) struct TimerKey(Instant, u64);
+
+/// Threads waiting for a deadline, ordered by their deadline.
+///
+/// Timers only unpark their thread, so a thread that wakes up before its
+/// timer fires should cancel it with [`Timers::remove`].
+#[derive(Default)]
+pub(crate) struct Timers {
+    entries: RefCell<BTreeMap<TimerKey, Thread>>,
+    next_id: Cell<u64>,
+}
+
+impl Timers {
+    pub fn insert(&self, deadline: Instant, thread: Thread) -> TimerKey {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let key = TimerKey(deadline, id);
+        self.entries.borrow_mut().insert(key, thread);
+        key
+    }
+
+    pub fn remove(&self, key: TimerKey) {
+        self.entries.borrow_mut().remove(&key);
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let entries = self.entries.borrow();
+        entries.first_key_value().map(|(key, _)| key.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    /// Unparks the threads whose deadline is before `now`, returning how many
+    /// timers fired.
+    pub fn fire(&self, now: Instant) -> usize {
+        let mut fired = 0;
+        loop {
+            let mut entries = self.entries.borrow_mut();
+            let Some(entry) = entries.first_entry() else {
+                return fired;
+            };
+            if entry.key().0 > now {
+                return fired;
+            }
+            let thread = entry.remove();
+            drop(entries);
+            thread.unpark();
+            fired += 1;
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::cell::Cell;
+use std::fmt;
+
+use super::wait_queue::WaitQueue;
+
+/// A barrier enables multiple green threads to synchronize the beginning
+/// of some computation.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::Barrier;
+/// use pneuma::thread;
+/// use std::rc::Rc;
+///
+/// let n = 10;
+/// let barrier = Rc::new(Barrier::new(n));
+///
+/// let handles: Vec<_> = (0..n)
+///     .map(|_| {
+///         let barrier = barrier.clone();
+///         // The same messages will be printed together.
+///         // You will NOT see any interleaving.
+///         thread::spawn(move || {
+///             println!("before wait");
+///             barrier.wait();
+///             println!("after wait");
+///         })
+///     })
+///     .collect();
+///
+/// // Wait for other threads to finish.
+/// for handle in handles {
+///     handle.join();
+/// }
+/// ```
+pub struct Barrier {
+    num_threads: usize,
+    count: Cell<usize>,
+    generation: Cell<usize>,
+    waiters: WaitQueue,
+}
+
+/// A `BarrierWaitResult` is returned by [`Barrier::wait()`] when all threads
+/// in the [`Barrier`] have rendezvoused.
+pub struct BarrierWaitResult(bool);
+
+impl Barrier {
+    /// Creates a new barrier that can park a given number of threads.
+    ///
+    /// A barrier will park `n`-1 threads which call [`wait()`] and then wake
+    /// up all threads at once when the `n`th thread calls [`wait()`].
+    ///
+    /// [`wait()`]: Barrier::wait
+    pub const fn new(n: usize) -> Barrier {
+        Barrier {
+            num_threads: n,
+            count: Cell::new(0),
+            generation: Cell::new(0),
+            waiters: WaitQueue::new("Barrier"),
+        }
+    }
+
+    /// Parks the current green thread until all threads have rendezvoused here.
+    ///
+    /// Barriers are re-usable after all threads have rendezvoused once, and can
+    /// be used continuously.
+    ///
+    /// A single (arbitrary) thread will receive a [`BarrierWaitResult`] that
+    /// returns `true` from [`BarrierWaitResult::is_leader()`] when returning
+    /// from this function, and all other threads will receive a result that
+    /// will return `false` from [`BarrierWaitResult::is_leader()`].
+    pub fn wait(&self) -> BarrierWaitResult {
+        crate::thread::consume_budget();
+        let generation = self.generation.get();
+        let count = self.count.get() + 1;
+        if count < self.num_threads {
+            self.count.set(count);
+            while generation == self.generation.get() {
+                self.waiters.wait();
+            }
+            BarrierWaitResult(false)
+        } else {
+            self.count.set(0);
+            self.generation.set(generation.wrapping_add(1));
+            self.waiters.notify_all();
+            BarrierWaitResult(true)
+        }
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Barrier").finish_non_exhaustive()
+    }
+}
+
+impl BarrierWaitResult {
+    /// Returns `true` if this thread is the "leader thread" for the call to
+    /// [`Barrier::wait()`].
+    ///
+    /// Only one thread will have `true` returned from their result, all other
+    /// threads will have `false` returned.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl fmt::Debug for BarrierWaitResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BarrierWaitResult")
+            .field("is_leader", &self.is_leader())
+            .finish()
+    }
+}
+
+#[test]
+fn barrier_elects_one_leader_per_generation() {
+    use pneuma::thread;
+    use std::rc::Rc;
+
+    let barrier = Rc::new(Barrier::new(3));
+    let handles: Vec<_> = (0..2)
+        .map(|_| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                (0..2)
+                    .map(|_| barrier.wait().is_leader())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+
+    let mut leaders = [0; 2];
+    for generation in &mut leaders {
+        *generation += barrier.wait().is_leader() as usize;
+    }
+    for handle in handles {
+        for (generation, is_leader) in handle.join().into_iter().enumerate() {
+            leaders[generation] += is_leader as usize;
+        }
+    }
+    assert_eq!(leaders, [1, 1]);
+}
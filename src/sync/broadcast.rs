@@ -0,0 +1,349 @@
+//! A multi-producer, multi-consumer broadcast channel for green threads.
+//!
+//! Each value sent is observed by every [`Receiver`] that was subscribed when
+//! it was sent. Values are stored in a bounded ring buffer: when the buffer is
+//! full, sending overwrites the oldest value. Receivers that fall behind miss
+//! the overwritten values, and are told how many values they missed through
+//! [`RecvError::Lagged`] before resuming with the oldest value still retained.
+//!
+//! Receivers park their green thread until a new value is published, or until
+//! all the senders are dropped.
+//!
+//! # Examples
+//!
+//! ```
+//! use pneuma::sync::broadcast;
+//! use pneuma::thread;
+//!
+//! let (tx, mut rx1) = broadcast::channel(16);
+//! let mut rx2 = tx.subscribe();
+//!
+//! let handle = thread::spawn(move || {
+//!     assert_eq!(rx1.recv().unwrap(), 10);
+//!     assert_eq!(rx1.recv().unwrap(), 20);
+//! });
+//!
+//! tx.send(10).unwrap();
+//! tx.send(20).unwrap();
+//!
+//! assert_eq!(rx2.recv().unwrap(), 10);
+//! assert_eq!(rx2.recv().unwrap(), 20);
+//! handle.join();
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+pub use std::sync::mpsc::SendError;
+
+use super::wait_queue::WaitQueue;
+
+struct Shared<T> {
+    buffer: RefCell<VecDeque<T>>,
+    capacity: usize,
+    /// The position of the oldest value in the buffer.
+    head: Cell<u64>,
+    senders: Cell<usize>,
+    receivers: Cell<usize>,
+    waiters: WaitQueue,
+}
+
+/// Sending-half of the [`broadcast`](self) channel.
+///
+/// It can be cloned to send values from several green threads.
+pub struct Sender<T> {
+    shared: Rc<Shared<T>>,
+}
+
+/// Receiving-half of the [`broadcast`](self) channel.
+///
+/// Cloning a receiver creates a new receiver at the same position in the
+/// channel. New receivers can also be created with [`Sender::subscribe`].
+pub struct Receiver<T> {
+    shared: Rc<Shared<T>>,
+    /// The position of the next value to receive.
+    next: u64,
+}
+
+/// An error returned from [`Receiver::recv`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecvError {
+    /// All the senders were dropped and there are no values left to receive.
+    Closed,
+    /// The receiver lagged too far behind, and the given number of values
+    /// were overwritten before it could receive them.
+    Lagged(u64),
+}
+
+/// An error returned from [`Receiver::try_recv`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    /// There are no new values to receive at the moment.
+    Empty,
+    /// All the senders were dropped and there are no values left to receive.
+    Closed,
+    /// The receiver lagged too far behind, and the given number of values
+    /// were overwritten before it could receive them.
+    Lagged(u64),
+}
+
+/// Creates a bounded broadcast channel which retains at most `capacity`
+/// values.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        capacity > 0,
+        "broadcast channel capacity must be greater than zero"
+    );
+    let shared = Rc::new(Shared {
+        buffer: RefCell::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        head: Cell::new(0),
+        senders: Cell::new(1),
+        receivers: Cell::new(1),
+        waiters: WaitQueue::new("broadcast::Receiver"),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, next: 0 },
+    )
+}
+
+impl<T> Shared<T> {
+    /// The position the next value sent will have.
+    fn tail(&self) -> u64 {
+        self.head.get() + self.buffer.borrow().len() as u64
+    }
+}
+
+impl<T: Clone> Sender<T> {
+    /// Sends a value to all the active receivers, returning how many receivers
+    /// will see it.
+    ///
+    /// This method never parks. If the buffer is full, the oldest value is
+    /// overwritten.
+    ///
+    /// # Errors
+    ///
+    /// Returns the value back if there are no active receivers.
+    pub fn send(&self, t: T) -> Result<usize, SendError<T>> {
+        crate::thread::consume_budget();
+        let receivers = self.shared.receivers.get();
+        if receivers == 0 {
+            return Err(SendError(t));
+        }
+        let mut buffer = self.shared.buffer.borrow_mut();
+        if buffer.len() == self.shared.capacity {
+            buffer.pop_front();
+            self.shared.head.set(self.shared.head.get() + 1);
+        }
+        buffer.push_back(t);
+        drop(buffer);
+        self.shared.waiters.notify_all();
+        Ok(receivers)
+    }
+
+    /// Creates a new receiver that will observe the values sent after this call.
+    pub fn subscribe(&self) -> Receiver<T> {
+        self.shared.receivers.set(self.shared.receivers.get() + 1);
+        Receiver {
+            shared: self.shared.clone(),
+            next: self.shared.tail(),
+        }
+    }
+
+    /// Returns the number of active receivers.
+    pub fn receiver_count(&self) -> usize {
+        self.shared.receivers.get()
+    }
+}
+
+impl<T: Clone> Receiver<T> {
+    /// Receives the next value, parking the current green thread until one is
+    /// sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecvError::Closed`] once all the senders are dropped and every
+    /// value has been received, and [`RecvError::Lagged`] if values were
+    /// overwritten before this receiver could see them. After a lag, the next
+    /// call returns the oldest value that is still retained.
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        crate::thread::consume_budget();
+        loop {
+            match self.try_recv_unbudgeted() {
+                Ok(t) => return Ok(t),
+                Err(TryRecvError::Closed) => return Err(RecvError::Closed),
+                Err(TryRecvError::Lagged(n)) => return Err(RecvError::Lagged(n)),
+                Err(TryRecvError::Empty) => self.shared.waiters.wait(),
+            }
+        }
+    }
+
+    /// Attempts to receive the next value without parking.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        crate::thread::consume_budget();
+        self.try_recv_unbudgeted()
+    }
+
+    /// Like [`try_recv`](Self::try_recv), without consuming the budget.
+    fn try_recv_unbudgeted(&mut self) -> Result<T, TryRecvError> {
+        let head = self.shared.head.get();
+        if self.next < head {
+            let missed = head - self.next;
+            self.next = head;
+            return Err(TryRecvError::Lagged(missed));
+        }
+        let buffer = self.shared.buffer.borrow();
+        match buffer.get((self.next - head) as usize) {
+            Some(t) => {
+                self.next += 1;
+                Ok(t.clone())
+            }
+            None if self.shared.senders.get() == 0 => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Returns the number of values sent that this receiver hasn't seen yet,
+    /// including the ones that were overwritten.
+    pub fn len(&self) -> usize {
+        (self.shared.tail() - self.next) as usize
+    }
+
+    /// Returns `true` if there are no values left to receive.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        self.shared.senders.set(self.shared.senders.get() + 1);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Receiver<T> {
+        self.shared.receivers.set(self.shared.receivers.get() + 1);
+        Receiver {
+            shared: self.shared.clone(),
+            next: self.next,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let senders = self.shared.senders.get() - 1;
+        self.shared.senders.set(senders);
+        if senders == 0 {
+            self.shared.waiters.notify_all();
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receivers.set(self.shared.receivers.get() - 1);
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Closed => "channel closed".fmt(f),
+            RecvError::Lagged(n) => write!(f, "channel lagged by {n}"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => "channel empty".fmt(f),
+            TryRecvError::Closed => "channel closed".fmt(f),
+            TryRecvError::Lagged(n) => write!(f, "channel lagged by {n}"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[test]
+fn slow_receiver_lags() {
+    let (tx, mut rx) = channel(2);
+    for i in 0..5 {
+        tx.send(i).unwrap();
+    }
+    assert_eq!(rx.recv(), Err(RecvError::Lagged(3)));
+    assert_eq!(rx.recv(), Ok(3));
+    assert_eq!(rx.recv(), Ok(4));
+    drop(tx);
+    assert_eq!(rx.recv(), Err(RecvError::Closed));
+}
+
+#[test]
+fn every_receiver_gets_every_value() {
+    use pneuma::thread;
+
+    let (tx, rx) = channel(4);
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
+            let mut rx = rx.clone();
+            thread::spawn(move || std::iter::from_fn(|| rx.recv().ok()).collect::<Vec<_>>())
+        })
+        .collect();
+    drop(rx);
+    thread::yield_now();
+    for i in 0..3 {
+        assert_eq!(tx.send(i), Ok(3));
+    }
+    drop(tx);
+    for handle in handles {
+        assert_eq!(handle.join(), [0, 1, 2]);
+    }
+}
+
+#[test]
+fn subscribe_only_sees_later_values() {
+    let (tx, mut rx) = channel(4);
+    tx.send(1).unwrap();
+    let mut late = tx.subscribe();
+    tx.send(2).unwrap();
+    assert_eq!(rx.try_recv(), Ok(1));
+    assert_eq!(late.try_recv(), Ok(2));
+    assert_eq!(late.try_recv(), Err(TryRecvError::Empty));
+}
+
+#[test]
+fn send_fails_without_receivers() {
+    let (tx, rx) = channel(1);
+    drop(rx);
+    assert_eq!(tx.send(1), Err(SendError(1)));
+}
@@ -0,0 +1,276 @@
+use std::fmt;
+use std::sync::LockResult;
+use std::time::{Duration, Instant};
+
+use super::mutex::{guard_lock, MutexGuard};
+use super::poison;
+use super::wait_queue::WaitQueue;
+
+/// A type indicating whether a timed wait on a condition variable returned
+/// due to a time out or not.
+///
+/// It is returned by the [`wait_timeout`] method.
+///
+/// [`wait_timeout`]: Condvar::wait_timeout
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    /// Returns `true` if the wait was known to have timed out.
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+/// A Condition Variable for green threads.
+///
+/// Condition variables represent the ability to park a green thread such that
+/// it consumes no CPU time while waiting for an event to occur. Condition
+/// variables are typically associated with a boolean predicate (a condition)
+/// and a [`Mutex`]. The predicate is always verified inside of the mutex before
+/// determining that a thread must park.
+///
+/// Unlike `std`'s condition variable, this one is implemented on top of
+/// [`park`] and [`Thread::unpark`], so waiting only parks the current green
+/// thread. It works with pneuma's [`MutexGuard`], and a condition variable may
+/// be used with more than one mutex.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::{Condvar, Mutex};
+/// use pneuma::thread;
+/// use std::rc::Rc;
+///
+/// let pair = Rc::new((Mutex::new(false), Condvar::new()));
+/// let pair2 = pair.clone();
+///
+/// // Inside of our lock, spawn a new thread, and then wait for it to start.
+/// thread::spawn(move || {
+///     let (lock, cvar) = &*pair2;
+///     let mut started = lock.lock().unwrap();
+///     *started = true;
+///     // We notify the condvar that the value has changed.
+///     cvar.notify_one();
+/// });
+///
+/// // Wait for the thread to start up.
+/// let (lock, cvar) = &*pair;
+/// let mut started = lock.lock().unwrap();
+/// while !*started {
+///     started = cvar.wait(started).unwrap();
+/// }
+/// ```
+///
+/// [`Mutex`]: super::Mutex
+/// [`park`]: crate::thread::park
+/// [`Thread::unpark`]: crate::thread::Thread::unpark
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Default for Condvar {
+    fn default() -> Condvar {
+        Condvar::new()
+    }
+}
+
+impl Condvar {
+    /// Creates a new condition variable which is ready to be waited on and
+    /// notified.
+    pub const fn new() -> Condvar {
+        Condvar {
+            waiters: WaitQueue::new("Condvar"),
+        }
+    }
+
+    /// Parks the current green thread until this condition variable receives a
+    /// notification.
+    ///
+    /// This function will atomically unlock the mutex specified (represented by
+    /// `guard`) and park the current thread. This means that any calls
+    /// to [`notify_one`] or [`notify_all`] which happen logically after the
+    /// mutex is unlocked are candidates to wake this thread up. When this
+    /// function call returns, the lock specified will have been re-acquired.
+    ///
+    /// Note that this function is susceptible to spurious wakeups. Condition
+    /// variables normally have a boolean predicate associated with them, and
+    /// the predicate must always be checked each time this function returns to
+    /// protect against spurious wakeups.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the mutex being waited on is
+    /// poisoned when this thread re-acquires the lock.
+    ///
+    /// [`notify_one`]: Self::notify_one
+    /// [`notify_all`]: Self::notify_all
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+        let lock = guard_lock(&guard);
+        drop(guard);
+        self.waiters.wait();
+        lock.lock()
+    }
+
+    /// Parks the current green thread until the provided condition becomes
+    /// false.
+    ///
+    /// `condition` is checked immediately; if not met (returns `true`), this
+    /// will [`wait`] for the next notification then check again. This repeats
+    /// until `condition` returns `false`, in which case this function returns.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the mutex being waited on is
+    /// poisoned when this thread re-acquires the lock.
+    ///
+    /// [`wait`]: Self::wait
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> LockResult<MutexGuard<'a, T>>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard)?;
+        }
+        Ok(guard)
+    }
+
+    /// Waits on this condition variable for a notification, timing out after a
+    /// specified duration.
+    ///
+    /// The semantics of this function are equivalent to [`wait`] except that
+    /// the thread will be parked for roughly no longer than `dur`.
+    ///
+    /// The returned [`WaitTimeoutResult`] value indicates if the timeout is
+    /// known to have elapsed.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the mutex being waited on is
+    /// poisoned when this thread re-acquires the lock.
+    ///
+    /// [`wait`]: Self::wait
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        dur: Duration,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        let lock = guard_lock(&guard);
+        let notified = match Instant::now().checked_add(dur) {
+            Some(deadline) => {
+                drop(guard);
+                self.waiters.wait_until(deadline)
+            }
+            None => {
+                drop(guard);
+                self.waiters.wait();
+                true
+            }
+        };
+        poison::map_result(lock.lock(), |guard| (guard, WaitTimeoutResult(!notified)))
+    }
+
+    /// Waits on this condition variable for a notification, timing out after a
+    /// specified duration.
+    ///
+    /// The semantics of this function are equivalent to [`wait_while`] except
+    /// that the thread will be parked for roughly no longer than `dur`.
+    ///
+    /// The returned [`WaitTimeoutResult`] value indicates if the timeout is
+    /// known to have elapsed without the condition being met.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the mutex being waited on is
+    /// poisoned when this thread re-acquires the lock.
+    ///
+    /// [`wait_while`]: Self::wait_while
+    pub fn wait_timeout_while<'a, T, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        dur: Duration,
+        mut condition: F,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let start = Instant::now();
+        loop {
+            if !condition(&mut *guard) {
+                return Ok((guard, WaitTimeoutResult(false)));
+            }
+            let timeout = match dur.checked_sub(start.elapsed()) {
+                Some(timeout) => timeout,
+                None => return Ok((guard, WaitTimeoutResult(true))),
+            };
+            guard = self.wait_timeout(guard, timeout)?.0;
+        }
+    }
+
+    /// Wakes up one green thread parked on this condvar.
+    ///
+    /// If there is a thread parked on this condition variable, then it will
+    /// be woken up from its call to [`wait`] or [`wait_timeout`]. Calls to
+    /// `notify_one` are not buffered in any way.
+    ///
+    /// [`wait`]: Self::wait
+    /// [`wait_timeout`]: Self::wait_timeout
+    pub fn notify_one(&self) {
+        self.waiters.notify_one();
+    }
+
+    /// Wakes up all green threads parked on this condvar.
+    ///
+    /// This method will ensure that any current waiters on the condition
+    /// variable are awoken. Calls to `notify_all()` are not buffered in any
+    /// way.
+    pub fn notify_all(&self) {
+        self.waiters.notify_all();
+    }
+}
+
+impl fmt::Debug for Condvar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Condvar").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn wait_timeout_times_out() {
+    use super::Mutex;
+    use pneuma::thread;
+    use std::rc::Rc;
+
+    let pair = Rc::new((Mutex::new(false), Condvar::new()));
+
+    // nobody notifies, so the wait runs out
+    let (lock, cvar) = &*pair;
+    let start = Instant::now();
+    let (guard, result) = cvar
+        .wait_timeout(lock.lock().unwrap(), Duration::from_millis(20))
+        .unwrap();
+    assert!(result.timed_out());
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    drop(guard);
+
+    // a notification arrives well before the timeout
+    let notifier = pair.clone();
+    let handle = thread::spawn(move || {
+        let (lock, cvar) = &*notifier;
+        *lock.lock().unwrap() = true;
+        cvar.notify_one();
+    });
+    let (guard, result) = cvar
+        .wait_timeout_while(lock.lock().unwrap(), Duration::from_secs(10), |ready| {
+            !*ready
+        })
+        .unwrap();
+    assert!(!result.timed_out());
+    assert!(*guard);
+    drop(guard);
+    handle.join();
+}
@@ -0,0 +1,38 @@
+//! Synchronization primitives for green threads.
+//!
+//! The primitives in this module are the green thread analog of the ones in
+//! [`std::sync`]. Instead of blocking the OS thread, they park the calling green
+//! thread, allowing the rest of the green threads running on the same OS thread
+//! to make progress. Using `std`'s blocking primitives from a green thread is
+//! discouraged: if the resource is held by another green thread on the same
+//! OS thread, blocking will deadlock the whole OS thread.
+//!
+//! Since green threads are scheduled on a single OS thread, these primitives
+//! are neither [`Send`] nor [`Sync`], and are meant to be shared through an
+//! [`Rc`](std::rc::Rc).
+//!
+//! The error types returned by the locks are the same as the ones in `std`,
+//! and they are re-exported here for convenience.
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use condvar::{Condvar, WaitTimeoutResult};
+pub use mutex::{Mutex, MutexGuard};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use select::Select;
+pub use semaphore::{Semaphore, SemaphorePermit};
+pub use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+pub use wait_group::WaitGroup;
+
+pub(crate) mod barrier;
+pub mod broadcast;
+pub(crate) mod condvar;
+pub mod mpsc;
+pub(crate) mod mutex;
+pub mod oneshot;
+pub(crate) mod poison;
+pub(crate) mod rwlock;
+pub(crate) mod select;
+pub(crate) mod semaphore;
+pub(crate) mod wait_group;
+pub(crate) mod wait_queue;
+pub mod watch;
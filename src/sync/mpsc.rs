@@ -0,0 +1,698 @@
+//! Multi-producer, single-consumer FIFO queue communication primitives for
+//! green threads.
+//!
+//! This module provides message-based communication over channels, concretely
+//! defined among three types:
+//!
+//! * [`Sender`]
+//! * [`SyncSender`]
+//! * [`Receiver`]
+//!
+//! A [`Sender`] or [`SyncSender`] is used to send data to a [`Receiver`]. Both
+//! senders are clone-able (multi-producer) such that many green threads can send
+//! simultaneously to one receiver (single-consumer).
+//!
+//! These channels come in two flavors:
+//!
+//! 1. An asynchronous, infinitely buffered channel. The [`channel`] function
+//!    will return a `(Sender, Receiver)` tuple where all sends will be
+//!    **asynchronous** (they never park). The channel conceptually has an
+//!    infinite buffer.
+//!
+//! 2. A synchronous, bounded channel. The [`sync_channel`] function will
+//!    return a `(SyncSender, Receiver)` tuple where the storage for pending
+//!    messages is a pre-allocated buffer of a fixed size. All sends will be
+//!    **synchronous** by parking until there is buffer space available. Note
+//!    that a bound of 0 is allowed, causing the channel to become a "rendezvous"
+//!    channel where each sender parks until the receiver takes the message.
+//!
+//! The API mirrors [`std::sync::mpsc`], and its error types are re-exported
+//! here. The difference is that the operations park the calling green thread
+//! rather than blocking the OS thread.
+//!
+//! ## Disconnection
+//!
+//! The send and receive operations on channels will all return a [`Result`]
+//! indicating whether the operation succeeded or not. An unsuccessful operation
+//! is normally indicative of the other half of a channel having "hung up" by
+//! being dropped in its corresponding green thread.
+//!
+//! # Examples
+//!
+//! ```
+//! use pneuma::sync::mpsc::channel;
+//! use pneuma::thread;
+//!
+//! // Create a shared channel that can be sent along from many threads
+//! // where tx is the sending half (tx for transmission), and rx is the receiving
+//! // half (rx for receiving).
+//! let (tx, rx) = channel();
+//! for i in 0..10 {
+//!     let tx = tx.clone();
+//!     thread::spawn(move || {
+//!         tx.send(i).unwrap();
+//!     });
+//! }
+//!
+//! for _ in 0..10 {
+//!     let j = rx.recv().unwrap();
+//!     assert!(0 <= j && j < 10);
+//! }
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+
+use super::wait_queue::{on_unwind, WaitQueue, Waiter};
+
+/// The state shared between the senders and the receiver of a channel.
+pub(crate) struct Shared<T> {
+    pub(crate) queue: RefCell<VecDeque<T>>,
+    /// The maximum number of buffered messages, or `None` if unbounded.
+    capacity: Option<usize>,
+    senders: Cell<usize>,
+    receiver: Cell<bool>,
+    /// The number of messages that have been sent and received respectively,
+    /// used by rendezvous senders to know when their message was taken.
+    sent: Cell<u64>,
+    received: Cell<u64>,
+    /// Receiver waiting for a message or for the senders to hang up.
+    pub(crate) recv_waiters: WaitQueue,
+    /// Senders waiting for space in the buffer.
+    pub(crate) send_waiters: WaitQueue,
+    /// Rendezvous senders waiting for their message to be received.
+    acks: WaitQueue,
+}
+
+/// The receiving half of a channel. This half can only be owned by one
+/// green thread.
+///
+/// Messages sent to the channel can be retrieved using [`recv`].
+///
+/// [`recv`]: Receiver::recv
+pub struct Receiver<T> {
+    pub(crate) shared: Rc<Shared<T>>,
+}
+
+/// The sending-half of the asynchronous [`channel`] type. This half can only
+/// be owned by one green thread, but it can be cloned to send to other threads.
+///
+/// Messages can be sent through this channel with [`send`].
+///
+/// [`send`]: Sender::send
+pub struct Sender<T> {
+    pub(crate) shared: Rc<Shared<T>>,
+}
+
+/// The sending-half of the synchronous [`sync_channel`] type.
+///
+/// Messages can be sent through this channel with [`send`] or [`try_send`].
+/// [`send`] will park if there is no space in the internal buffer.
+///
+/// [`send`]: SyncSender::send
+/// [`try_send`]: SyncSender::try_send
+pub struct SyncSender<T> {
+    pub(crate) shared: Rc<Shared<T>>,
+}
+
+/// Creates a new asynchronous channel, returning the sender/receiver halves.
+/// All data sent on the [`Sender`] will become available on the [`Receiver`] in
+/// the same order as it was sent, and no [`send`] will park the calling thread
+/// (this channel has an "infinite buffer", unlike [`sync_channel`], which will
+/// park after its buffer limit is reached). [`recv`] will park until a message
+/// is available while there is at least one [`Sender`] alive.
+///
+/// If the [`Receiver`] is disconnected while trying to [`send`] with the
+/// [`Sender`], the [`send`] method will return a [`SendError`]. Similarly, if
+/// the [`Sender`] is disconnected while trying to [`recv`], the [`recv`] method
+/// will return a [`RecvError`].
+///
+/// [`send`]: Sender::send
+/// [`recv`]: Receiver::recv
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::mpsc::channel;
+/// use pneuma::thread;
+///
+/// let (sender, receiver) = channel();
+///
+/// // Spawn off an expensive computation
+/// thread::spawn(move || {
+///     sender.send(53).unwrap();
+/// });
+///
+/// assert_eq!(receiver.recv().unwrap(), 53);
+/// ```
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Shared::new(None);
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// Creates a new synchronous, bounded channel.
+/// All data sent on the [`SyncSender`] will become available on the [`Receiver`]
+/// in the same order as it was sent. Like asynchronous [`channel`]s, the
+/// [`Receiver`] will park until a message becomes available. `sync_channel`
+/// differs greatly in the semantics of the sender, however.
+///
+/// This channel has an internal buffer on which messages will be queued.
+/// `bound` specifies the buffer size. When the internal buffer becomes full,
+/// future sends will park waiting for the buffer to open up. Note that a
+/// buffer size of 0 is valid, in which case this becomes "rendezvous channel"
+/// where each [`send`] will not return until a [`recv`] is paired with it.
+///
+/// Like asynchronous channels, if the [`Receiver`] is disconnected while trying
+/// to [`send`] with the [`SyncSender`], the [`send`] method will return a
+/// [`SendError`]. Similarly, If the [`SyncSender`] is disconnected while trying
+/// to [`recv`], the [`recv`] method will return a [`RecvError`].
+///
+/// [`send`]: SyncSender::send
+/// [`recv`]: Receiver::recv
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::mpsc::sync_channel;
+/// use pneuma::thread;
+///
+/// let (sender, receiver) = sync_channel(1);
+///
+/// // this returns immediately
+/// sender.send(1).unwrap();
+///
+/// thread::spawn(move || {
+///     // this will park until the previous message has been received
+///     sender.send(2).unwrap();
+/// });
+///
+/// assert_eq!(receiver.recv().unwrap(), 1);
+/// assert_eq!(receiver.recv().unwrap(), 2);
+/// ```
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
+    let shared = Shared::new(Some(bound));
+    (
+        SyncSender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Shared<T> {
+    fn new(capacity: Option<usize>) -> Rc<Shared<T>> {
+        Rc::new(Shared {
+            queue: RefCell::new(VecDeque::new()),
+            capacity,
+            senders: Cell::new(1),
+            receiver: Cell::new(true),
+            sent: Cell::new(0),
+            received: Cell::new(0),
+            recv_waiters: WaitQueue::new("mpsc::Receiver"),
+            send_waiters: WaitQueue::new("mpsc::SyncSender"),
+            acks: WaitQueue::new("mpsc::SyncSender"),
+        })
+    }
+
+    pub(crate) fn is_disconnected(&self) -> bool {
+        self.senders.get() == 0
+    }
+
+    pub(crate) fn is_receiver_dropped(&self) -> bool {
+        !self.receiver.get()
+    }
+
+    /// Whether a message can be pushed without exceeding the capacity.
+    /// Rendezvous channels can hold a single message that is being handed off.
+    pub(crate) fn has_space(&self) -> bool {
+        match self.capacity {
+            None => true,
+            Some(capacity) => self.queue.borrow().len() < capacity.max(1),
+        }
+    }
+
+    fn is_rendezvous(&self) -> bool {
+        self.capacity == Some(0)
+    }
+
+    /// Registers a waiting receiver. Rendezvous senders can only hand off
+    /// their message to a waiting receiver, so one of them is woken up.
+    pub(crate) fn register_receiver(&self, waiter: Rc<Waiter>) {
+        self.recv_waiters.push(waiter);
+        if self.is_rendezvous() {
+            self.send_waiters.notify_one();
+        }
+    }
+
+    /// Parks the receiver until a message is sent, the senders hang up, or the
+    /// deadline is reached. Returns `false` if the deadline was reached.
+    fn wait_recv(&self, deadline: Option<Instant>) -> bool {
+        let waiter = Waiter::new("mpsc::Receiver");
+        self.register_receiver(waiter.clone());
+        let remove = || self.recv_waiters.remove(&waiter);
+        let Some(deadline) = deadline else {
+            on_unwind(|| waiter.wait(), remove);
+            return true;
+        };
+        let notified = on_unwind(|| waiter.wait_until(deadline), remove);
+        if !notified {
+            self.recv_waiters.remove(&waiter);
+        }
+        notified
+    }
+
+    /// Pushes a message, returning its sequence number.
+    pub(crate) fn push(&self, t: T) -> u64 {
+        self.queue.borrow_mut().push_back(t);
+        let seq = self.sent.get();
+        self.sent.set(seq + 1);
+        self.recv_waiters.notify_one();
+        seq
+    }
+
+    pub(crate) fn pop(&self) -> Option<T> {
+        let t = self.queue.borrow_mut().pop_front()?;
+        self.received.set(self.received.get() + 1);
+        self.send_waiters.notify_one();
+        if self.is_rendezvous() {
+            self.acks.notify_all();
+        }
+        Some(t)
+    }
+
+    fn send(&self, t: T) -> Result<(), SendError<T>> {
+        loop {
+            if self.is_receiver_dropped() {
+                return Err(SendError(t));
+            }
+            if self.has_space() {
+                break;
+            }
+            self.send_waiters.wait();
+        }
+        let seq = self.push(t);
+        if self.is_rendezvous() {
+            self.wait_ack(seq)?;
+        }
+        Ok(())
+    }
+
+    /// Parks a rendezvous sender until its message is received. If the
+    /// receiver hangs up first, the message is taken back.
+    pub(crate) fn wait_ack(&self, seq: u64) -> Result<(), SendError<T>> {
+        while self.received.get() <= seq {
+            if self.is_receiver_dropped() {
+                let t = self.queue.borrow_mut().pop_front().unwrap();
+                return Err(SendError(t));
+            }
+            self.acks.wait();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn try_recv(&self) -> Result<T, TryRecvError> {
+        match self.pop() {
+            Some(t) => Ok(t),
+            None if self.is_disconnected() => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub(crate) fn try_send(&self, t: T) -> Result<(), TrySendError<T>> {
+        if self.is_receiver_dropped() {
+            return Err(TrySendError::Disconnected(t));
+        }
+        // A rendezvous only succeeds if the receiver is already waiting.
+        let ready = if self.is_rendezvous() {
+            self.queue.borrow().is_empty() && !self.recv_waiters.is_empty()
+        } else {
+            self.has_space()
+        };
+        if !ready {
+            return Err(TrySendError::Full(t));
+        }
+        self.push(t);
+        Ok(())
+    }
+
+    fn drop_sender(&self) {
+        let senders = self.senders.get() - 1;
+        self.senders.set(senders);
+        if senders == 0 {
+            self.recv_waiters.notify_all();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Attempts to send a value on this channel, returning it back if it could
+    /// not be sent.
+    ///
+    /// A successful send occurs when it is determined that the other end of
+    /// the channel has not hung up already. An unsuccessful send would be one
+    /// where the corresponding receiver has already been deallocated. Note
+    /// that a return value of [`Err`] means that the data will never be
+    /// received, but a return value of [`Ok`] does *not* mean that the data
+    /// will be received. It is possible for the corresponding receiver to
+    /// hang up immediately after this function returns [`Ok`].
+    ///
+    /// This method will never park the current thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::sync::mpsc::channel;
+    ///
+    /// let (tx, rx) = channel();
+    ///
+    /// // This send is always successful
+    /// tx.send(1).unwrap();
+    ///
+    /// // This send will fail because the receiver is gone
+    /// drop(rx);
+    /// assert_eq!(tx.send(1).unwrap_err().0, 1);
+    /// ```
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        crate::thread::consume_budget();
+        self.shared.send(t)
+    }
+}
+
+impl<T> SyncSender<T> {
+    /// Sends a value on this synchronous channel.
+    ///
+    /// This function will *park* until space in the internal buffer becomes
+    /// available or a receiver is available to hand off the message to.
+    ///
+    /// Note that a successful send does *not* guarantee that the receiver will
+    /// ever see the data if there is a buffer on this channel. Items may be
+    /// enqueued in the internal buffer for the receiver to receive at a later
+    /// time. If the buffer size is 0, however, the channel becomes a rendezvous
+    /// channel and it guarantees that the receiver has indeed received
+    /// the data if this function returns success.
+    ///
+    /// This function will never panic, but it may return [`Err`] if the
+    /// [`Receiver`] has disconnected and is no longer able to receive
+    /// information.
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        crate::thread::consume_budget();
+        self.shared.send(t)
+    }
+
+    /// Attempts to send a value on this channel without parking.
+    ///
+    /// This method differs from [`send`] by returning immediately if the
+    /// channel's buffer is full or no receiver is waiting to acquire some
+    /// data. Compared with [`send`], this function has two failure cases
+    /// instead of one (one for disconnection, one for a full buffer).
+    ///
+    /// [`send`]: Self::send
+    pub fn try_send(&self, t: T) -> Result<(), TrySendError<T>> {
+        crate::thread::consume_budget();
+        self.shared.try_send(t)
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Attempts to return a pending value on this receiver without parking.
+    ///
+    /// This method will never park the caller in order to wait for data to
+    /// become available. Instead, this will always return immediately with a
+    /// possible option of pending data on the channel.
+    ///
+    /// This is useful for a flavor of "optimistic check" before deciding to
+    /// park on a receiver.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        crate::thread::consume_budget();
+        self.shared.try_recv()
+    }
+
+    /// Attempts to wait for a value on this receiver, returning an error if the
+    /// corresponding channel has hung up.
+    ///
+    /// This function will always park the current green thread if there is no
+    /// data available and it's possible for more data to be sent (at least one
+    /// sender still exists). Once a message is sent to the corresponding
+    /// [`Sender`] (or [`SyncSender`]), this receiver will wake up and return
+    /// that message.
+    ///
+    /// If the corresponding [`Sender`] has disconnected, or it disconnects while
+    /// this call is parked, this call will wake up and return [`Err`] to
+    /// indicate that no more messages can ever be received on this channel.
+    /// However, since channels are buffered, messages sent before the disconnect
+    /// will still be properly received.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        crate::thread::consume_budget();
+        loop {
+            match self.shared.try_recv() {
+                Ok(t) => return Ok(t),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => {
+                    self.shared.wait_recv(None);
+                }
+            }
+        }
+    }
+
+    /// Attempts to wait for a value on this receiver, returning an error if the
+    /// corresponding channel has hung up, or if it waits more than `timeout`.
+    ///
+    /// This function will always park the current green thread if there is no
+    /// data available and it's possible for more data to be sent (at least one
+    /// sender still exists). Once a message is sent to the corresponding
+    /// [`Sender`] (or [`SyncSender`]), this receiver will wake up and return
+    /// that message.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::sync::mpsc::{channel, RecvTimeoutError};
+    /// use std::time::Duration;
+    ///
+    /// let (send, recv) = channel::<()>();
+    ///
+    /// assert_eq!(
+    ///     recv.recv_timeout(Duration::from_millis(10)),
+    ///     Err(RecvTimeoutError::Timeout)
+    /// );
+    /// ```
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.recv_deadline(deadline),
+            None => self.recv().map_err(RecvTimeoutError::from),
+        }
+    }
+
+    /// Attempts to wait for a value on this receiver, returning an error if the
+    /// corresponding channel has hung up, or if `deadline` is reached.
+    pub fn recv_deadline(&self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        crate::thread::consume_budget();
+        loop {
+            match self.shared.try_recv() {
+                Ok(t) => return Ok(t),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => {
+                    if !self.shared.wait_recv(Some(deadline)) {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns an iterator that will park waiting for messages, but never
+    /// [`panic!`]. It will return [`None`] when the channel has hung up.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+
+    /// Returns an iterator that will attempt to yield all pending values for a
+    /// receiver, possibly returning [`None`] if there are no more pending values.
+    /// It will never [`panic!`] or park the user by waiting for values.
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+}
+
+/// An iterator over messages on a [`Receiver`], created by [`iter`].
+///
+/// This iterator will park whenever [`next`] is called, waiting for a new
+/// message, and [`None`] will be returned when the corresponding channel has
+/// hung up.
+///
+/// [`iter`]: Receiver::iter
+/// [`next`]: Iterator::next
+#[derive(Debug)]
+pub struct Iter<'a, T: 'a> {
+    rx: &'a Receiver<T>,
+}
+
+/// An iterator that attempts to yield all pending values for a [`Receiver`],
+/// created by [`try_iter`].
+///
+/// [`None`] will be returned when there are no pending values remaining or if
+/// the corresponding channel has hung up.
+///
+/// [`try_iter`]: Receiver::try_iter
+#[derive(Debug)]
+pub struct TryIter<'a, T: 'a> {
+    rx: &'a Receiver<T>,
+}
+
+/// An owning iterator over messages on a [`Receiver`], created by
+/// [`into_iter`](IntoIterator::into_iter).
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    rx: Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { rx: self }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        self.shared.senders.set(self.shared.senders.get() + 1);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> SyncSender<T> {
+        self.shared.senders.set(self.shared.senders.get() + 1);
+        SyncSender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.drop_sender();
+    }
+}
+
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        self.shared.drop_sender();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver.set(false);
+        self.shared.send_waiters.notify_all();
+        self.shared.acks.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for SyncSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncSender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn rendezvous_send_waits_for_recv() {
+    use pneuma::thread;
+
+    let (tx, rx) = sync_channel(0);
+    let (done_tx, done_rx) = channel();
+
+    thread::spawn(move || {
+        tx.send(1).unwrap();
+        done_tx.send(()).unwrap();
+    });
+
+    thread::yield_now();
+    assert_eq!(done_rx.try_recv(), Err(TryRecvError::Empty));
+    assert_eq!(rx.recv(), Ok(1));
+    assert_eq!(done_rx.recv(), Ok(()));
+}
+
+#[test]
+fn unbounded_send_loop_yields_once_the_budget_is_exhausted() {
+    use pneuma::runtime;
+    use pneuma::thread;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    runtime::Builder::new().budget(Some(4)).install().unwrap();
+
+    let (tx, rx) = channel();
+    let sent = Rc::new(Cell::new(0));
+    let count = sent.clone();
+    let sender = thread::spawn(move || {
+        for i in 0..100 {
+            tx.send(i).unwrap();
+            count.set(count.get() + 1);
+        }
+    });
+    let count = sent.clone();
+    let other = thread::spawn(move || count.get());
+    sender.join();
+    assert_eq!(other.join(), 4);
+    assert_eq!(rx.iter().count(), 100);
+}
@@ -0,0 +1,348 @@
+use std::cell::{Cell, UnsafeCell};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+use super::poison;
+use super::wait_queue::WaitQueue;
+
+/// A mutual exclusion primitive useful for protecting shared data between
+/// green threads.
+///
+/// This mutex will park green threads waiting for the lock to become available,
+/// letting other green threads on the same OS thread run in the meantime. This
+/// is what sets it apart from [`std::sync::Mutex`], which blocks the whole OS
+/// thread and deadlocks if the lock is held by another green thread.
+///
+/// Waiting threads acquire the lock in the order they requested it. When the lock
+/// is released and there are waiters, ownership is handed off directly to the
+/// first one, so a thread that repeatedly locks and unlocks the mutex cannot
+/// starve the rest.
+///
+/// # Poisoning
+///
+/// Like `std`'s mutex, this mutex is poisoned whenever a thread panics while
+/// holding it. Once a mutex is poisoned, all other threads are unable to access
+/// the data by default, as it is likely tainted. The [`PoisonError`] type has an
+/// [`into_inner`](PoisonError::into_inner) method which will return the guard
+/// that would have otherwise been returned on a successful lock.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::Mutex;
+/// use pneuma::thread;
+/// use std::rc::Rc;
+///
+/// let counter = Rc::new(Mutex::new(0));
+///
+/// let handles: Vec<_> = (0..10)
+///     .map(|_| {
+///         let counter = counter.clone();
+///         thread::spawn(move || {
+///             let mut count = counter.lock().unwrap();
+///             thread::yield_now();
+///             *count += 1;
+///         })
+///     })
+///     .collect();
+///
+/// for handle in handles {
+///     handle.join();
+/// }
+/// assert_eq!(*counter.lock().unwrap(), 10);
+/// ```
+pub struct Mutex<T: ?Sized> {
+    locked: Cell<bool>,
+    poison: poison::Flag,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a "scoped lock" of a mutex. When this structure is
+/// dropped (falls out of scope), the lock will be unlocked.
+///
+/// The data protected by the mutex can be accessed through this guard via its
+/// [`Deref`] and [`DerefMut`] implementations.
+///
+/// This structure is created by the [`lock`] and [`try_lock`] methods on
+/// [`Mutex`].
+///
+/// [`lock`]: Mutex::lock
+/// [`try_lock`]: Mutex::try_lock
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a Mutex<T>,
+    poison: poison::Guard,
+}
+
+impl<T> Mutex<T> {
+    /// Creates a new mutex in an unlocked state ready for use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::sync::Mutex;
+    ///
+    /// let mutex = Mutex::new(0);
+    /// ```
+    pub const fn new(t: T) -> Mutex<T> {
+        Mutex {
+            locked: Cell::new(false),
+            poison: poison::Flag::new(),
+            waiters: WaitQueue::new("Mutex"),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    /// Consumes this mutex, returning the underlying data.
+    ///
+    /// # Errors
+    ///
+    /// If another thread panicked while holding this mutex, then this call will
+    /// return an error containing the underlying data instead.
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.poison.get();
+        let data = self.data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Acquires the mutex, parking the current green thread until it is able to do so.
+    ///
+    /// Upon returning, the thread is the only thread with the lock held. An RAII
+    /// guard is returned to allow scoped unlock of the lock. When the guard goes
+    /// out of scope, the mutex will be unlocked.
+    ///
+    /// Attempting to lock a mutex in a thread which already holds the lock will
+    /// result in a deadlock.
+    ///
+    /// # Errors
+    ///
+    /// If another thread panicked while holding this mutex, then this call will
+    /// return an error once the mutex is acquired.
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        crate::thread::consume_budget();
+        if self.locked.replace(true) {
+            // The lock will be handed off to us once we are notified.
+            self.waiters.wait();
+        }
+        unsafe { MutexGuard::new(self) }
+    }
+
+    /// Attempts to acquire this lock.
+    ///
+    /// If the lock could not be acquired at this time, then [`Err`] is returned.
+    /// Otherwise, an RAII guard is returned. The lock will be unlocked when the
+    /// guard is dropped.
+    ///
+    /// This function does not park.
+    ///
+    /// # Errors
+    ///
+    /// If another thread panicked while holding this mutex, then this call will
+    /// return the [`Poisoned`] error if the mutex would otherwise be acquired.
+    ///
+    /// If the mutex could not be acquired because it is already locked, then
+    /// this call will return the [`WouldBlock`] error.
+    ///
+    /// [`Poisoned`]: TryLockError::Poisoned
+    /// [`WouldBlock`]: TryLockError::WouldBlock
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        crate::thread::consume_budget();
+        self.try_lock_unbudgeted()
+    }
+
+    /// Like [`try_lock`](Self::try_lock), without consuming the budget.
+    fn try_lock_unbudgeted(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        if self.locked.replace(true) {
+            return Err(TryLockError::WouldBlock);
+        }
+        Ok(unsafe { MutexGuard::new(self) }?)
+    }
+
+    /// Determines whether the mutex is poisoned.
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Clear the poisoned state from a mutex.
+    ///
+    /// If the mutex is poisoned, it will remain poisoned until this function is
+    /// called. This allows recovering from a poisoned state and marking that it
+    /// has recovered.
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the `Mutex` mutably, no actual locking needs to
+    /// take place.
+    ///
+    /// # Errors
+    ///
+    /// If another thread panicked while holding this mutex, then this call will
+    /// return an error containing a mutable reference to the underlying data
+    /// instead.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let data = self.data.get_mut();
+        if self.poison.get() {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Releases the lock, handing it off to the first waiter if there is one.
+    fn unlock(&self) {
+        if !self.waiters.notify_one() {
+            self.locked.set(false);
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Mutex<T> {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T> From<T> for Mutex<T> {
+    fn from(t: T) -> Self {
+        Mutex::new(t)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock_unbudgeted() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(err)) => d.field("data", &&**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.poison.get());
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<'mutex, T: ?Sized> MutexGuard<'mutex, T> {
+    /// # Safety
+    /// The lock must be held by the current thread.
+    unsafe fn new(lock: &'mutex Mutex<T>) -> LockResult<MutexGuard<'mutex, T>> {
+        poison::map_result(lock.poison.guard(), |poison| MutexGuard { lock, poison })
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.poison.done(&self.poison);
+        self.lock.unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+pub(crate) fn guard_lock<'a, T: ?Sized>(guard: &MutexGuard<'a, T>) -> &'a Mutex<T> {
+    guard.lock
+}
+
+#[test]
+fn lock_is_handed_off_in_order() {
+    use pneuma::thread;
+    use std::rc::Rc;
+
+    let mutex = Rc::new(Mutex::new(vec![]));
+    let guard = mutex.lock().unwrap();
+
+    let handles: Vec<_> = (0..3)
+        .map(|i| {
+            let mutex = mutex.clone();
+            thread::spawn(move || mutex.lock().unwrap().push(i))
+        })
+        .collect();
+
+    // let every thread queue up on the lock
+    thread::yield_now();
+    drop(guard);
+
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(*mutex.lock().unwrap(), [0, 1, 2]);
+}
+
+#[test]
+fn uncontended_lock_is_not_a_cancellation_point() {
+    use pneuma::thread::{self, Cancel};
+
+    let mutex = Mutex::new(0);
+    let handle = thread::spawn(move || {
+        thread::current().cancel(Cancel::Unwind);
+        for _ in 0..1000 {
+            *mutex.lock().unwrap() += 1;
+        }
+        mutex.into_inner().unwrap()
+    });
+    assert_eq!(handle.try_join().unwrap(), 1000);
+}
+
+#[test]
+fn lock_yields_once_the_budget_is_exhausted() {
+    use pneuma::runtime;
+    use pneuma::thread::{self, Cancel};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    runtime::Builder::new().budget(Some(2)).install().unwrap();
+
+    let mutex = Rc::new(Mutex::new(()));
+    let turns = Rc::new(RefCell::new(Vec::new()));
+    let (locker, log) = (mutex.clone(), turns.clone());
+    let handle = thread::spawn(move || {
+        // yielding for the budget doesn't unwind cancelled threads
+        thread::current().cancel(Cancel::Unwind);
+        for _ in 0..4 {
+            drop(locker.lock().unwrap());
+            log.borrow_mut().push("locker");
+        }
+    });
+    let log = turns.clone();
+    let other = thread::spawn(move || log.borrow_mut().push("other"));
+    handle.try_join().unwrap();
+    other.join();
+    assert_eq!(
+        *turns.borrow(),
+        ["locker", "locker", "other", "locker", "locker"]
+    );
+}
@@ -0,0 +1,195 @@
+//! A channel for sending a single value between green threads.
+//!
+//! The [`channel`] function returns a [`Sender`] and [`Receiver`] pair. Sending
+//! consumes the sender and never parks, while the receiver parks until the
+//! value is sent or the sender is dropped.
+//!
+//! This is mostly useful for getting the result of an operation performed by
+//! another green thread, where the full [`mpsc`](super::mpsc) machinery is not
+//! needed.
+//!
+//! # Examples
+//!
+//! ```
+//! use pneuma::sync::oneshot;
+//! use pneuma::thread;
+//!
+//! let (tx, rx) = oneshot::channel();
+//!
+//! thread::spawn(move || {
+//!     tx.send("done").unwrap();
+//! });
+//!
+//! assert_eq!(rx.recv(), Ok("done"));
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+
+use super::wait_queue::WaitQueue;
+
+pub(crate) struct Shared<T> {
+    value: RefCell<Option<T>>,
+    sender: Cell<bool>,
+    receiver: Cell<bool>,
+    pub(crate) waiters: WaitQueue,
+}
+
+/// Sends a single value to the associated [`Receiver`].
+///
+/// This half is created by the [`channel`] function.
+pub struct Sender<T> {
+    shared: Rc<Shared<T>>,
+}
+
+/// Receives the single value sent by the associated [`Sender`].
+///
+/// This half is created by the [`channel`] function.
+pub struct Receiver<T> {
+    pub(crate) shared: Rc<Shared<T>>,
+}
+
+/// Creates a new oneshot channel, returning the sender/receiver halves.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::oneshot;
+///
+/// let (tx, rx) = oneshot::channel();
+/// tx.send(1).unwrap();
+/// assert_eq!(rx.recv(), Ok(1));
+/// ```
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(Shared {
+        value: RefCell::new(None),
+        sender: Cell::new(true),
+        receiver: Cell::new(true),
+        waiters: WaitQueue::new("oneshot::Receiver"),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Shared<T> {
+    pub(crate) fn take(&self) -> Result<T, TryRecvError> {
+        match self.value.borrow_mut().take() {
+            Some(t) => Ok(t),
+            None if !self.sender.get() => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Sends the value to the receiver, consuming the sender.
+    ///
+    /// This method never parks. It returns the value back if the [`Receiver`]
+    /// has already been dropped.
+    pub fn send(self, t: T) -> Result<(), SendError<T>> {
+        crate::thread::consume_budget();
+        if !self.shared.receiver.get() {
+            return Err(SendError(t));
+        }
+        *self.shared.value.borrow_mut() = Some(t);
+        Ok(())
+    }
+
+    /// Returns `true` if the associated [`Receiver`] has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.receiver.get()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Waits for the value, parking the current green thread until it is sent.
+    ///
+    /// If the [`Sender`] is dropped without sending a value, this returns
+    /// [`RecvError`].
+    pub fn recv(self) -> Result<T, RecvError> {
+        crate::thread::consume_budget();
+        loop {
+            match self.shared.take() {
+                Ok(t) => return Ok(t),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+                Err(TryRecvError::Empty) => self.shared.waiters.wait(),
+            }
+        }
+    }
+
+    /// Attempts to take the value without parking.
+    ///
+    /// Once the value has been taken, subsequent calls return
+    /// [`TryRecvError::Disconnected`].
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        crate::thread::consume_budget();
+        self.shared.take()
+    }
+
+    /// Waits for the value, parking the current green thread for at most
+    /// `timeout`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::sync::oneshot::{self, RecvTimeoutError};
+    /// use std::time::Duration;
+    ///
+    /// let (_tx, rx) = oneshot::channel::<()>();
+    /// assert_eq!(
+    ///     rx.recv_timeout(Duration::from_millis(10)),
+    ///     Err(RecvTimeoutError::Timeout)
+    /// );
+    /// ```
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        crate::thread::consume_budget();
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            match self.shared.take() {
+                Ok(t) => return Ok(t),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => match deadline {
+                    Some(deadline) => {
+                        if !self.shared.waiters.wait_until(deadline) {
+                            return Err(RecvTimeoutError::Timeout);
+                        }
+                    }
+                    None => self.shared.waiters.wait(),
+                },
+            }
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.sender.set(false);
+        self.shared.waiters.notify_all();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver.set(false);
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,58 @@
+use std::cell::Cell;
+use std::sync::{LockResult, PoisonError};
+use std::thread;
+
+/// Poison flag shared by the green locks. It follows the same rules as the
+/// one in `std`: a lock is poisoned if a thread panics while holding it.
+pub(crate) struct Flag {
+    failed: Cell<bool>,
+}
+
+/// Records whether the thread was already panicking when the lock was
+/// acquired, so that only new panics poison the lock.
+pub(crate) struct Guard {
+    panicking: bool,
+}
+
+impl Flag {
+    pub const fn new() -> Flag {
+        Flag {
+            failed: Cell::new(false),
+        }
+    }
+
+    pub fn guard(&self) -> LockResult<Guard> {
+        let guard = Guard {
+            panicking: thread::panicking(),
+        };
+        if self.get() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    pub fn done(&self, guard: &Guard) {
+        if !guard.panicking && thread::panicking() {
+            self.failed.set(true);
+        }
+    }
+
+    pub fn get(&self) -> bool {
+        self.failed.get()
+    }
+
+    pub fn clear(&self) {
+        self.failed.set(false)
+    }
+}
+
+pub(crate) fn map_result<T, U, F>(result: LockResult<T>, f: F) -> LockResult<U>
+where
+    F: FnOnce(T) -> U,
+{
+    match result {
+        Ok(t) => Ok(f(t)),
+        Err(err) => Err(PoisonError::new(f(err.into_inner()))),
+    }
+}
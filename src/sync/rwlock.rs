@@ -0,0 +1,417 @@
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+use super::poison;
+use super::wait_queue::{on_unwind, Waiter};
+
+/// The lock state value used when a writer holds the lock.
+const WRITE_LOCKED: isize = -1;
+
+/// A reader-writer lock for green threads.
+///
+/// This type of lock allows a number of readers or at most one writer at any
+/// point in time. The write portion of this lock typically allows modification
+/// of the underlying data (exclusive access) and the read portion of this lock
+/// typically allows for read-only access (shared access).
+///
+/// Threads waiting for the lock are parked and served in the order they arrived,
+/// so writers cannot be starved by a steady stream of readers: once a writer is
+/// waiting, new readers queue up behind it. When the lock is released it is
+/// handed off directly to the next writer, or to all the consecutive readers at
+/// the front of the queue.
+///
+/// # Poisoning
+///
+/// An `RwLock`, like [`Mutex`], will become poisoned on a panic. Note, however,
+/// that an `RwLock` may only be poisoned if a panic occurs while it is locked
+/// exclusively (write mode). If a panic occurs in any reader, then the lock
+/// will not be poisoned.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::RwLock;
+///
+/// let lock = RwLock::new(5);
+///
+/// // many reader locks can be held at once
+/// {
+///     let r1 = lock.read().unwrap();
+///     let r2 = lock.read().unwrap();
+///     assert_eq!(*r1, 5);
+///     assert_eq!(*r2, 5);
+/// } // read locks are dropped at this point
+///
+/// // only one write lock may be held, however
+/// {
+///     let mut w = lock.write().unwrap();
+///     *w += 1;
+///     assert_eq!(*w, 6);
+/// } // write lock is dropped here
+/// ```
+///
+/// [`Mutex`]: super::Mutex
+pub struct RwLock<T: ?Sized> {
+    /// The number of readers holding the lock, or `WRITE_LOCKED`.
+    state: Cell<isize>,
+    poison: poison::Flag,
+    waiters: RefCell<VecDeque<(Access, Rc<Waiter>)>>,
+    data: UnsafeCell<T>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+}
+
+/// RAII structure used to release the shared read access of a lock when
+/// dropped.
+///
+/// This structure is created by the [`read`] and [`try_read`] methods on
+/// [`RwLock`].
+///
+/// [`read`]: RwLock::read
+/// [`try_read`]: RwLock::try_read
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+
+/// RAII structure used to release the exclusive write access of a lock when
+/// dropped.
+///
+/// This structure is created by the [`write`] and [`try_write`] methods
+/// on [`RwLock`].
+///
+/// [`write`]: RwLock::write
+/// [`try_write`]: RwLock::try_write
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+    poison: poison::Guard,
+}
+
+impl<T> RwLock<T> {
+    /// Creates a new instance of an `RwLock<T>` which is unlocked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::sync::RwLock;
+    ///
+    /// let lock = RwLock::new(5);
+    /// ```
+    pub const fn new(t: T) -> RwLock<T> {
+        RwLock {
+            state: Cell::new(0),
+            poison: poison::Flag::new(),
+            waiters: RefCell::new(VecDeque::new()),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    /// Consumes this `RwLock`, returning the underlying data.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error containing the underlying data if
+    /// the `RwLock` is poisoned.
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.poison.get();
+        let data = self.data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Locks this `RwLock` with shared read access, parking the current green
+    /// thread until it can be acquired.
+    ///
+    /// The calling thread will be parked until there are no more writers which
+    /// hold the lock, and no writers waiting for it. There may be other readers
+    /// currently inside the lock when this method returns.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `RwLock` is poisoned. An
+    /// `RwLock` is poisoned whenever a writer panics while holding an exclusive
+    /// lock. The failure will occur immediately after the lock has been
+    /// acquired.
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        crate::thread::consume_budget();
+        let state = self.state.get();
+        if state >= 0 && self.waiters.borrow().is_empty() {
+            self.state.set(state + 1);
+        } else {
+            self.wait(Access::Read);
+        }
+        unsafe { RwLockReadGuard::new(self) }
+    }
+
+    /// Attempts to acquire this `RwLock` with shared read access.
+    ///
+    /// If the access could not be granted at this time, then `Err` is returned.
+    /// Otherwise, an RAII guard is returned which will release the shared access
+    /// when it is dropped.
+    ///
+    /// This function does not park.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the [`Poisoned`] error if the `RwLock` is
+    /// poisoned, and the [`WouldBlock`] error if the `RwLock` could not be
+    /// acquired because it was already locked exclusively or a writer is
+    /// waiting for it.
+    ///
+    /// [`Poisoned`]: TryLockError::Poisoned
+    /// [`WouldBlock`]: TryLockError::WouldBlock
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        crate::thread::consume_budget();
+        self.try_read_unbudgeted()
+    }
+
+    /// Like [`try_read`](Self::try_read), without consuming the budget.
+    fn try_read_unbudgeted(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        let state = self.state.get();
+        if state < 0 || !self.waiters.borrow().is_empty() {
+            return Err(TryLockError::WouldBlock);
+        }
+        self.state.set(state + 1);
+        Ok(unsafe { RwLockReadGuard::new(self) }?)
+    }
+
+    /// Locks this `RwLock` with exclusive write access, parking the current
+    /// green thread until it can be acquired.
+    ///
+    /// This function will not return while other writers or other readers
+    /// currently have access to the lock.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the `RwLock` is poisoned. An
+    /// `RwLock` is poisoned whenever a writer panics while holding an exclusive
+    /// lock. An error will be returned when the lock is acquired.
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        crate::thread::consume_budget();
+        if self.state.get() == 0 && self.waiters.borrow().is_empty() {
+            self.state.set(WRITE_LOCKED);
+        } else {
+            self.wait(Access::Write);
+        }
+        unsafe { RwLockWriteGuard::new(self) }
+    }
+
+    /// Attempts to lock this `RwLock` with exclusive write access.
+    ///
+    /// If the lock could not be acquired at this time, then `Err` is returned.
+    /// Otherwise, an RAII guard is returned which will release the lock when
+    /// it is dropped.
+    ///
+    /// This function does not park.
+    ///
+    /// # Errors
+    ///
+    /// This function will return the [`Poisoned`] error if the `RwLock` is
+    /// poisoned, and the [`WouldBlock`] error if the `RwLock` could not be
+    /// acquired because it was already locked.
+    ///
+    /// [`Poisoned`]: TryLockError::Poisoned
+    /// [`WouldBlock`]: TryLockError::WouldBlock
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        crate::thread::consume_budget();
+        if self.state.get() != 0 {
+            return Err(TryLockError::WouldBlock);
+        }
+        self.state.set(WRITE_LOCKED);
+        Ok(unsafe { RwLockWriteGuard::new(self) }?)
+    }
+
+    /// Determines whether the lock is poisoned.
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Clear the poisoned state from a lock.
+    ///
+    /// If the lock is poisoned, it will remain poisoned until this function is
+    /// called. This allows recovering from a poisoned state and marking that it
+    /// has recovered.
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the `RwLock` mutably, no actual locking needs to
+    /// take place.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error containing a mutable reference to
+    /// the underlying data if the `RwLock` is poisoned.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let data = self.data.get_mut();
+        if self.poison.get() {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Parks the current thread until the lock is handed off to it.
+    fn wait(&self, access: Access) {
+        let waiter = Waiter::new("RwLock");
+        self.waiters
+            .borrow_mut()
+            .push_back((access, waiter.clone()));
+        on_unwind(
+            || waiter.wait(),
+            || {
+                let mut waiters = self.waiters.borrow_mut();
+                waiters.retain(|(_, other)| !Rc::ptr_eq(other, &waiter));
+            },
+        );
+    }
+
+    fn read_unlock(&self) {
+        let state = self.state.get() - 1;
+        self.state.set(state);
+        if state == 0 {
+            self.wake_next();
+        }
+    }
+
+    fn write_unlock(&self) {
+        self.state.set(0);
+        self.wake_next();
+    }
+
+    /// Hands the unlocked lock off to the next writer, or to every reader
+    /// at the front of the queue.
+    fn wake_next(&self) {
+        let mut waiters = self.waiters.borrow_mut();
+        if let Some((Access::Write, waiter)) = waiters.front() {
+            self.state.set(WRITE_LOCKED);
+            waiter.notify();
+            waiters.pop_front();
+            return;
+        }
+        while let Some((Access::Read, waiter)) = waiters.front() {
+            self.state.set(self.state.get() + 1);
+            waiter.notify();
+            waiters.pop_front();
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> RwLock<T> {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T> From<T> for RwLock<T> {
+    fn from(t: T) -> Self {
+        RwLock::new(t)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.try_read_unbudgeted() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(err)) => d.field("data", &&**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.poison.get());
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<'rwlock, T: ?Sized> RwLockReadGuard<'rwlock, T> {
+    /// # Safety
+    /// The current thread must hold a read lock.
+    unsafe fn new(lock: &'rwlock RwLock<T>) -> LockResult<RwLockReadGuard<'rwlock, T>> {
+        poison::map_result(lock.poison.guard(), |_| RwLockReadGuard { lock })
+    }
+}
+
+impl<'rwlock, T: ?Sized> RwLockWriteGuard<'rwlock, T> {
+    /// # Safety
+    /// The current thread must hold the write lock.
+    unsafe fn new(lock: &'rwlock RwLock<T>) -> LockResult<RwLockWriteGuard<'rwlock, T>> {
+        poison::map_result(lock.poison.guard(), |poison| RwLockWriteGuard {
+            lock,
+            poison,
+        })
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.poison.done(&self.poison);
+        self.lock.write_unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
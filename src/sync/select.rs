@@ -0,0 +1,405 @@
+//! Waiting on multiple operations at once.
+//!
+//! See [`Select`] for details.
+
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use pneuma::runtime;
+use pneuma::thread;
+
+use super::mpsc::{self, RecvError, SendError, TryRecvError, TrySendError};
+use super::oneshot;
+use super::wait_queue::{on_unwind, WaitQueue, Waiter, WaitingOn};
+
+/// Waits on several channel operations, timers and cancellation at once.
+///
+/// Operations are added through the builder methods, each with a handler that
+/// receives the result of the operation. Calling [`wait`] parks the current
+/// green thread until one of the operations can complete, completes it, and
+/// returns the output of its handler. Only one operation is ever completed,
+/// and the thread is deregistered from the rest before returning.
+///
+/// If more than one operation is ready, the one that was added first is
+/// completed.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::mpsc::channel;
+/// use pneuma::sync::Select;
+/// use pneuma::thread;
+/// use std::time::Duration;
+///
+/// let (tx1, rx1) = channel();
+/// let (_tx2, rx2) = channel::<i32>();
+///
+/// thread::spawn(move || tx1.send(10).unwrap());
+///
+/// let out = Select::new()
+///     .recv(&rx1, |msg| msg.unwrap())
+///     .recv(&rx2, |msg| msg.unwrap() * 2)
+///     .timeout(Duration::from_secs(1), || panic!("timed out"))
+///     .wait();
+///
+/// assert_eq!(out, 10);
+/// ```
+///
+/// [`wait`]: Select::wait
+pub struct Select<'a, R> {
+    operations: Vec<Box<dyn Operation<R> + 'a>>,
+    deadline: Option<(Instant, Box<dyn FnOnce() -> R + 'a>)>,
+    cancelled: Option<Box<dyn FnOnce() -> R + 'a>>,
+}
+
+/// An operation that may be selected.
+trait Operation<R> {
+    /// Completes the operation if it is ready.
+    fn try_complete(&mut self) -> Option<R>;
+
+    /// The queue that is notified when the operation may become ready.
+    fn queue(&self) -> &WaitQueue;
+
+    fn register(&self, waiter: Rc<Waiter>) {
+        self.queue().push(waiter);
+    }
+}
+
+struct RecvOperation<'a, T, F> {
+    rx: &'a mpsc::Receiver<T>,
+    handler: Option<F>,
+}
+
+struct SendOperation<'a, T, F> {
+    tx: &'a mpsc::SyncSender<T>,
+    msg: Option<T>,
+    handler: Option<F>,
+}
+
+struct OneshotOperation<'a, T, F> {
+    rx: &'a oneshot::Receiver<T>,
+    handler: Option<F>,
+}
+
+impl<'a, R> Select<'a, R> {
+    /// Creates an empty selection.
+    pub fn new() -> Select<'a, R> {
+        Select {
+            operations: Vec::new(),
+            deadline: None,
+            cancelled: None,
+        }
+    }
+
+    /// Adds a receive operation on an [`mpsc`] channel.
+    ///
+    /// The handler receives the message, or [`RecvError`] if all the senders
+    /// hung up.
+    pub fn recv<T, F>(mut self, rx: &'a mpsc::Receiver<T>, handler: F) -> Self
+    where
+        F: FnOnce(Result<T, RecvError>) -> R + 'a,
+        T: 'a,
+    {
+        self.operations.push(Box::new(RecvOperation {
+            rx,
+            handler: Some(handler),
+        }));
+        self
+    }
+
+    /// Adds a send operation on a bounded [`mpsc`] channel.
+    ///
+    /// The handler receives the result of sending the message. Sending on a
+    /// rendezvous channel completes once a receiver is waiting for the message,
+    /// but unlike [`SyncSender::send`](mpsc::SyncSender::send), it doesn't
+    /// wait for the message to be received.
+    pub fn send<T, F>(mut self, tx: &'a mpsc::SyncSender<T>, msg: T, handler: F) -> Self
+    where
+        F: FnOnce(Result<(), SendError<T>>) -> R + 'a,
+        T: 'a,
+    {
+        self.operations.push(Box::new(SendOperation {
+            tx,
+            msg: Some(msg),
+            handler: Some(handler),
+        }));
+        self
+    }
+
+    /// Adds a receive operation on a [`oneshot`] channel.
+    ///
+    /// The handler receives the value, or [`RecvError`] if the sender was
+    /// dropped without sending it.
+    pub fn recv_oneshot<T, F>(mut self, rx: &'a oneshot::Receiver<T>, handler: F) -> Self
+    where
+        F: FnOnce(Result<T, RecvError>) -> R + 'a,
+        T: 'a,
+    {
+        self.operations.push(Box::new(OneshotOperation {
+            rx,
+            handler: Some(handler),
+        }));
+        self
+    }
+
+    /// Completes the selection once `timeout` elapses. If called more than
+    /// once, only the last timeout is kept.
+    pub fn timeout<F>(self, timeout: Duration, handler: F) -> Self
+    where
+        F: FnOnce() -> R + 'a,
+    {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.deadline(deadline, handler),
+            None => self,
+        }
+    }
+
+    /// Completes the selection once `deadline` is reached. If called more than
+    /// once, only the last deadline is kept.
+    pub fn deadline<F>(mut self, deadline: Instant, handler: F) -> Self
+    where
+        F: FnOnce() -> R + 'a,
+    {
+        self.deadline = Some((deadline, Box::new(handler)));
+        self
+    }
+
+    /// Completes the selection if the current thread is [cancelled].
+    ///
+    /// [cancelled]: crate::thread::Thread::cancel
+    pub fn cancelled<F>(mut self, handler: F) -> Self
+    where
+        F: FnOnce() -> R + 'a,
+    {
+        self.cancelled = Some(Box::new(handler));
+        self
+    }
+
+    /// Parks the current thread until one of the operations completes,
+    /// returning the output of its handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no operations were added, as the thread would never be
+    /// woken up.
+    pub fn wait(mut self) -> R {
+        crate::thread::consume_budget();
+        assert!(
+            !self.operations.is_empty() || self.deadline.is_some() || self.cancelled.is_some(),
+            "cannot select over an empty set of operations"
+        );
+        let rt = runtime::current();
+        // The operation whose queue notified us, if any.
+        let mut notifier = None;
+        loop {
+            if let Some((completed, out)) = self.try_complete() {
+                // We consumed a notification meant for an operation we didn't
+                // complete, so we pass it along to the next thread in its
+                // queue.
+                if let Some(notifier) = notifier.filter(|&notifier| Some(notifier) != completed) {
+                    self.operations[notifier].queue().notify_one();
+                }
+                return out;
+            }
+            let waiter = Waiter::new("Select");
+            for operation in &self.operations {
+                operation.register(waiter.clone());
+            }
+
+            let remove = || {
+                for operation in &self.operations {
+                    operation.queue().remove(&waiter);
+                }
+            };
+            let thread = thread::current();
+            on_unwind(
+                || {
+                    let _waiting = WaitingOn::new(&thread, "Select");
+                    match &self.deadline {
+                        Some((deadline, _)) => {
+                            rt.park_timeout(deadline.saturating_duration_since(Instant::now()))
+                        }
+                        None => rt.park(),
+                    }
+                },
+                remove,
+            );
+            remove();
+            notifier = self
+                .operations
+                .iter()
+                .position(|operation| waiter.is_notified_by(operation.queue()));
+        }
+    }
+
+    /// Completes the first operation that is ready without parking, returning
+    /// the index of the operation, if it wasn't the timeout or cancellation,
+    /// along with the output of its handler.
+    fn try_complete(&mut self) -> Option<(Option<usize>, R)> {
+        if self.cancelled.is_some() && thread::is_cancelled() {
+            return self.cancelled.take().map(|handler| (None, handler()));
+        }
+        for (index, operation) in self.operations.iter_mut().enumerate() {
+            if let Some(out) = operation.try_complete() {
+                return Some((Some(index), out));
+            }
+        }
+        match &self.deadline {
+            Some((deadline, _)) if *deadline <= Instant::now() => {
+                self.deadline.take().map(|(_, handler)| (None, handler()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<R> Default for Select<'_, R> {
+    fn default() -> Self {
+        Select::new()
+    }
+}
+
+impl<R> fmt::Debug for Select<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Select")
+            .field("operations", &self.operations.len())
+            .field(
+                "deadline",
+                &self.deadline.as_ref().map(|(deadline, _)| deadline),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F, R> Operation<R> for RecvOperation<'_, T, F>
+where
+    F: FnOnce(Result<T, RecvError>) -> R,
+{
+    fn try_complete(&mut self) -> Option<R> {
+        let result = match self.rx.shared.try_recv() {
+            Ok(t) => Ok(t),
+            Err(TryRecvError::Disconnected) => Err(RecvError),
+            Err(TryRecvError::Empty) => return None,
+        };
+        self.handler.take().map(|handler| handler(result))
+    }
+
+    fn queue(&self) -> &WaitQueue {
+        &self.rx.shared.recv_waiters
+    }
+
+    fn register(&self, waiter: Rc<Waiter>) {
+        self.rx.shared.register_receiver(waiter);
+    }
+}
+
+impl<T, F, R> Operation<R> for SendOperation<'_, T, F>
+where
+    F: FnOnce(Result<(), SendError<T>>) -> R,
+{
+    fn try_complete(&mut self) -> Option<R> {
+        let msg = self.msg.take()?;
+        let result = match self.tx.shared.try_send(msg) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Disconnected(msg)) => Err(SendError(msg)),
+            Err(TrySendError::Full(msg)) => {
+                self.msg = Some(msg);
+                return None;
+            }
+        };
+        self.handler.take().map(|handler| handler(result))
+    }
+
+    fn queue(&self) -> &WaitQueue {
+        &self.tx.shared.send_waiters
+    }
+}
+
+impl<T, F, R> Operation<R> for OneshotOperation<'_, T, F>
+where
+    F: FnOnce(Result<T, RecvError>) -> R,
+{
+    fn try_complete(&mut self) -> Option<R> {
+        let result = match self.rx.shared.take() {
+            Ok(t) => Ok(t),
+            Err(TryRecvError::Disconnected) => Err(RecvError),
+            Err(TryRecvError::Empty) => return None,
+        };
+        self.handler.take().map(|handler| handler(result))
+    }
+
+    fn queue(&self) -> &WaitQueue {
+        &self.rx.shared.waiters
+    }
+}
+
+#[test]
+fn select_completes_the_ready_operation() {
+    use super::mpsc::channel;
+    use std::cell::Cell;
+
+    let (tx1, rx1) = channel::<i32>();
+    let (tx2, rx2) = channel();
+    let handle = thread::spawn(move || tx2.send(2).unwrap());
+    let out = Select::new()
+        .recv(&rx1, |msg| msg.unwrap())
+        .recv(&rx2, |msg| msg.unwrap())
+        .wait();
+    assert_eq!(out, 2);
+    handle.join();
+
+    // nothing is ready, so the timeout completes
+    let timed_out = Cell::new(false);
+    Select::new()
+        .recv(&rx1, |_| unreachable!())
+        .timeout(Duration::from_millis(10), || timed_out.set(true))
+        .wait();
+    assert!(timed_out.get());
+    drop(tx1);
+}
+
+#[test]
+fn select_passes_on_unconsumed_notifications() {
+    use super::mpsc::{channel, sync_channel};
+
+    let (tx, rx) = sync_channel(1);
+    tx.send(0).unwrap();
+    let (other_tx, other_rx) = channel();
+
+    // The selecting thread queues up first to send on the full channel.
+    let selector_tx = tx.clone();
+    let selector = thread::spawn(move || {
+        Select::new()
+            .recv(&other_rx, |msg| msg.unwrap())
+            .send(&selector_tx, 1, |_| unreachable!())
+            .wait()
+    });
+    thread::yield_now();
+    let sender = thread::spawn(move || tx.send(2).unwrap());
+    thread::yield_now();
+
+    // Making room notifies the selector, which then completes its receive
+    // instead, so the sender must get the notification.
+    assert_eq!(rx.recv().unwrap(), 0);
+    other_tx.send(3).unwrap();
+    assert_eq!(selector.join(), 3);
+    sender.join();
+    assert_eq!(rx.recv().unwrap(), 2);
+}
+
+#[test]
+fn select_completes_on_cancellation() {
+    use super::mpsc::channel;
+
+    let (_tx, rx) = channel::<i32>();
+    let handle = thread::spawn(move || {
+        Select::new()
+            .recv(&rx, |_| "message")
+            .cancelled(|| "cancelled")
+            .wait()
+    });
+    thread::yield_now();
+    handle.cancel(thread::Cancel::FlagOnly);
+    assert_eq!(handle.join(), "cancelled");
+}
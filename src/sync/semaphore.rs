@@ -0,0 +1,228 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+use super::wait_queue::{on_unwind, Waiter};
+
+/// A counting semaphore for green threads.
+///
+/// A semaphore holds a number of permits, which threads acquire to proceed and
+/// give back once they are done. Threads that request more permits than are
+/// available are parked until enough permits are released. It is typically
+/// used to bound the concurrency of some operation, such as the number of
+/// outgoing requests in flight.
+///
+/// Permits are handed out in the order they were requested: a thread asking for
+/// many permits is not starved by threads asking for fewer of them.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::Semaphore;
+/// use pneuma::thread;
+/// use std::rc::Rc;
+///
+/// let semaphore = Rc::new(Semaphore::new(2));
+///
+/// let handles: Vec<_> = (0..8)
+///     .map(|_| {
+///         let semaphore = semaphore.clone();
+///         thread::spawn(move || {
+///             let _permit = semaphore.acquire();
+///             // at most two threads are here at any given time
+///             thread::yield_now();
+///         })
+///     })
+///     .collect();
+///
+/// for handle in handles {
+///     handle.join();
+/// }
+/// assert_eq!(semaphore.available_permits(), 2);
+/// ```
+pub struct Semaphore {
+    permits: Cell<usize>,
+    waiters: RefCell<VecDeque<(usize, Rc<Waiter>)>>,
+}
+
+/// An RAII guard holding permits acquired from a [`Semaphore`]. The permits
+/// are given back to the semaphore when the guard is dropped.
+///
+/// This structure is created by the [`acquire`] and [`acquire_many`] methods,
+/// and their non-parking variants.
+///
+/// [`acquire`]: Semaphore::acquire
+/// [`acquire_many`]: Semaphore::acquire_many
+#[must_use = "if unused the permits will immediately be released"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl Semaphore {
+    /// Creates a new semaphore with the given number of permits.
+    pub const fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            permits: Cell::new(permits),
+            waiters: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the number of permits that can currently be acquired.
+    pub fn available_permits(&self) -> usize {
+        self.permits.get()
+    }
+
+    /// Acquires a single permit, parking the current green thread until one is
+    /// available.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Acquires `n` permits, parking the current green thread until they are
+    /// all available.
+    ///
+    /// Permits are acquired all at once, so a thread never holds some of the
+    /// permits while waiting for the rest.
+    pub fn acquire_many(&self, n: usize) -> SemaphorePermit<'_> {
+        crate::thread::consume_budget();
+        if let Some(permit) = self.try_acquire_many_unbudgeted(n) {
+            return permit;
+        }
+        let waiter = Waiter::new("Semaphore");
+        self.waiters.borrow_mut().push_back((n, waiter.clone()));
+        // the permits are handed off to us once we are notified
+        on_unwind(
+            || waiter.wait(),
+            || {
+                let mut waiters = self.waiters.borrow_mut();
+                waiters.retain(|(_, other)| !Rc::ptr_eq(other, &waiter));
+                drop(waiters);
+                // the threads queued behind us may be served now
+                self.add_permits(0);
+            },
+        );
+        SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        }
+    }
+
+    /// Attempts to acquire a single permit without parking.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Attempts to acquire `n` permits without parking.
+    ///
+    /// This fails if there are not enough permits available, or if other
+    /// threads are already waiting for permits.
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        crate::thread::consume_budget();
+        self.try_acquire_many_unbudgeted(n)
+    }
+
+    /// Like [`try_acquire_many`](Self::try_acquire_many), without consuming
+    /// the budget.
+    fn try_acquire_many_unbudgeted(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        let permits = self.permits.get();
+        if permits < n || !self.waiters.borrow().is_empty() {
+            return None;
+        }
+        self.permits.set(permits - n);
+        Some(SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    /// Adds `n` new permits to the semaphore, waking up the threads that can
+    /// now proceed.
+    pub fn add_permits(&self, n: usize) {
+        self.permits.set(self.permits.get() + n);
+        let mut waiters = self.waiters.borrow_mut();
+        while let Some((wanted, waiter)) = waiters.front() {
+            let permits = self.permits.get();
+            if permits < *wanted {
+                break;
+            }
+            self.permits.set(permits - wanted);
+            waiter.notify();
+            waiters.pop_front();
+        }
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.permits.get())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SemaphorePermit<'_> {
+    /// Returns the number of permits held by this guard.
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Forgets the permits without giving them back to the semaphore, which
+    /// permanently reduces the number of available permits.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits != 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
+
+impl fmt::Debug for SemaphorePermit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.permits)
+            .finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn acquire_many_is_served_in_order() {
+    use pneuma::thread;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let semaphore = Rc::new(Semaphore::new(0));
+    let order = Rc::new(RefCell::new(vec![]));
+    let handles: Vec<_> = [3, 1]
+        .into_iter()
+        .map(|n| {
+            let semaphore = semaphore.clone();
+            let order = order.clone();
+            thread::spawn(move || {
+                let _permit = semaphore.acquire_many(n);
+                order.borrow_mut().push(n);
+            })
+        })
+        .collect();
+    thread::yield_now();
+
+    // the single permit is not enough for the first thread, and the second
+    // one doesn't get ahead of it
+    semaphore.add_permits(1);
+    thread::yield_now();
+    assert!(order.borrow().is_empty());
+    assert!(semaphore.try_acquire().is_none());
+
+    semaphore.add_permits(2);
+    for handle in handles {
+        handle.join();
+    }
+    assert_eq!(*order.borrow(), [3, 1]);
+    assert_eq!(semaphore.available_permits(), 3);
+}
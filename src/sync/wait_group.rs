@@ -0,0 +1,122 @@
+use std::cell::Cell;
+use std::fmt;
+
+use super::wait_queue::WaitQueue;
+
+/// Waits for a collection of green threads to finish.
+///
+/// This is modeled after Go's `sync.WaitGroup`. The counter is incremented
+/// with [`add`] before starting the work, each unit of work calls [`done`] when
+/// it finishes, and [`wait`] parks until the counter drops to zero.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::sync::WaitGroup;
+/// use pneuma::thread;
+/// use std::rc::Rc;
+///
+/// let wg = Rc::new(WaitGroup::new());
+///
+/// for _ in 0..4 {
+///     wg.add(1);
+///     let wg = wg.clone();
+///     thread::spawn(move || {
+///         // some work here
+///         wg.done();
+///     });
+/// }
+///
+/// wg.wait();
+/// ```
+///
+/// [`add`]: WaitGroup::add
+/// [`done`]: WaitGroup::done
+/// [`wait`]: WaitGroup::wait
+pub struct WaitGroup {
+    count: Cell<usize>,
+    waiters: WaitQueue,
+}
+
+impl Default for WaitGroup {
+    fn default() -> WaitGroup {
+        WaitGroup::new()
+    }
+}
+
+impl WaitGroup {
+    /// Creates a wait group with a counter of zero.
+    pub const fn new() -> WaitGroup {
+        WaitGroup {
+            count: Cell::new(0),
+            waiters: WaitQueue::new("WaitGroup"),
+        }
+    }
+
+    /// Adds `n` to the counter.
+    pub fn add(&self, n: usize) {
+        self.count.set(self.count.get() + n);
+    }
+
+    /// Decrements the counter by one, waking up the waiting threads if it
+    /// reaches zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the counter is already zero.
+    pub fn done(&self) {
+        let count = self.count.get();
+        assert!(
+            count != 0,
+            "WaitGroup::done called more times than WaitGroup::add"
+        );
+        self.count.set(count - 1);
+        if count == 1 {
+            self.waiters.notify_all();
+        }
+    }
+
+    /// Parks the current green thread until the counter is zero.
+    pub fn wait(&self) {
+        crate::thread::consume_budget();
+        while self.count.get() != 0 {
+            self.waiters.wait();
+        }
+    }
+
+    /// Returns the current value of the counter.
+    pub fn count(&self) -> usize {
+        self.count.get()
+    }
+}
+
+impl fmt::Debug for WaitGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WaitGroup")
+            .field("count", &self.count.get())
+            .finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn wait_returns_once_all_work_is_done() {
+    use pneuma::thread;
+    use std::rc::Rc;
+
+    let wg = Rc::new(WaitGroup::new());
+    wg.add(2);
+    let handles: Vec<_> = (0..2)
+        .map(|_| {
+            let wg = wg.clone();
+            thread::spawn(move || {
+                thread::yield_now();
+                wg.done();
+            })
+        })
+        .collect();
+    wg.wait();
+    assert_eq!(wg.count(), 0);
+    for handle in handles {
+        handle.join();
+    }
+}
@@ -0,0 +1,193 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Instant;
+
+use pneuma::runtime;
+use pneuma::thread::{self, Thread};
+
+/// A green thread waiting to be notified.
+///
+/// A waiter is notified at most once. Notifying a waiter unparks its thread,
+/// so the thread must always check [`Waiter::is_notified`] after waking up,
+/// as [`park`](thread::park) may return spuriously.
+///
+/// Waiting is not interrupted by [`Cancel::Unwind`](thread::Cancel::Unwind),
+/// but [`park`](thread::park) panics when it detects a deadlock, so a thread
+/// must leave its queue if it unwinds while waiting, see [`on_unwind`].
+pub(crate) struct Waiter {
+    thread: Thread,
+    notified: Cell<bool>,
+    /// The queue that notified the waiter, only compared against queues and
+    /// never dereferenced.
+    notified_by: Cell<*const WaitQueue>,
+    /// The primitive the thread waits on, reported in deadlocks and dumps.
+    what: &'static str,
+}
+
+impl Waiter {
+    /// Creates a waiter for the current green thread, waiting on `what`.
+    pub fn new(what: &'static str) -> Rc<Waiter> {
+        Rc::new(Waiter {
+            thread: thread::current(),
+            notified: Cell::new(false),
+            notified_by: Cell::new(std::ptr::null()),
+            what,
+        })
+    }
+
+    pub fn is_notified(&self) -> bool {
+        self.notified.get()
+    }
+
+    /// Returns whether the waiter was notified by `queue`.
+    pub fn is_notified_by(&self, queue: &WaitQueue) -> bool {
+        std::ptr::eq(self.notified_by.get(), queue)
+    }
+
+    /// Notifies the waiter, returning `false` if it had already been notified.
+    pub fn notify(&self) -> bool {
+        if self.notified.replace(true) {
+            return false;
+        }
+        self.thread.unpark();
+        true
+    }
+
+    /// Parks the current thread until the waiter is notified.
+    pub fn wait(&self) {
+        let rt = runtime::current();
+        let _waiting = WaitingOn::new(&self.thread, self.what);
+        while !self.is_notified() {
+            rt.park();
+        }
+    }
+
+    /// Parks the current thread until the waiter is notified or the deadline
+    /// is reached. Returns whether the waiter was notified.
+    pub fn wait_until(&self, deadline: Instant) -> bool {
+        let rt = runtime::current();
+        let _waiting = WaitingOn::new(&self.thread, self.what);
+        while !self.is_notified() {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            rt.park_timeout(deadline - now);
+        }
+        true
+    }
+}
+
+/// Records what a thread waits on, for deadlock reports and dumps, until it
+/// is dropped, even if the thread unwinds.
+pub(crate) struct WaitingOn<'a>(&'a Thread);
+
+impl<'a> WaitingOn<'a> {
+    pub fn new(thread: &'a Thread, what: &'static str) -> WaitingOn<'a> {
+        thread.set_waiting_on(Some(what));
+        WaitingOn(thread)
+    }
+}
+
+impl Drop for WaitingOn<'_> {
+    fn drop(&mut self) {
+        self.0.set_waiting_on(None);
+    }
+}
+
+/// Runs `wait`, calling `cleanup` if it unwinds.
+///
+/// A thread unwinds out of a wait when the runtime detects a deadlock, and
+/// its waiter must be removed from the queue then. Otherwise the primitive
+/// would later hand itself off to a thread that stopped waiting, and stay
+/// taken for good.
+pub(crate) fn on_unwind<R>(wait: impl FnOnce() -> R, cleanup: impl FnOnce()) -> R {
+    struct Guard<F: FnOnce()>(Option<F>);
+    impl<F: FnOnce()> Drop for Guard<F> {
+        fn drop(&mut self) {
+            if let Some(cleanup) = self.0.take() {
+                cleanup();
+            }
+        }
+    }
+
+    let mut guard = Guard(Some(cleanup));
+    let out = wait();
+    guard.0 = None;
+    out
+}
+
+/// A FIFO queue of parked green threads.
+///
+/// This is the building block for all the synchronization primitives in
+/// [`pneuma::sync`](crate::sync).
+pub(crate) struct WaitQueue {
+    waiters: RefCell<VecDeque<Rc<Waiter>>>,
+    /// The primitive the queue belongs to.
+    what: &'static str,
+}
+
+impl WaitQueue {
+    pub const fn new(what: &'static str) -> WaitQueue {
+        WaitQueue {
+            waiters: RefCell::new(VecDeque::new()),
+            what,
+        }
+    }
+
+    pub fn push(&self, waiter: Rc<Waiter>) {
+        self.waiters.borrow_mut().push_back(waiter);
+    }
+
+    /// Parks the current thread at the back of the queue until it is notified.
+    pub fn wait(&self) {
+        let waiter = Waiter::new(self.what);
+        self.push(waiter.clone());
+        on_unwind(|| waiter.wait(), || self.remove(&waiter));
+    }
+
+    /// Parks the current thread at the back of the queue until it is notified
+    /// or the deadline is reached. Returns whether the thread was notified.
+    pub fn wait_until(&self, deadline: Instant) -> bool {
+        let waiter = Waiter::new(self.what);
+        self.push(waiter.clone());
+        let notified = on_unwind(|| waiter.wait_until(deadline), || self.remove(&waiter));
+        if !notified {
+            self.remove(&waiter);
+        }
+        notified
+    }
+
+    /// Wakes up the first waiter in the queue that wasn't notified already.
+    /// Returns whether a thread was woken up.
+    pub fn notify_one(&self) -> bool {
+        loop {
+            let Some(waiter) = self.waiters.borrow_mut().pop_front() else {
+                return false;
+            };
+            if waiter.notify() {
+                waiter.notified_by.set(self);
+                return true;
+            }
+        }
+    }
+
+    /// Wakes up all the threads in the queue, returning how many were woken up.
+    pub fn notify_all(&self) -> usize {
+        let waiters = std::mem::take(&mut *self.waiters.borrow_mut());
+        waiters.into_iter().filter(|waiter| waiter.notify()).count()
+    }
+
+    /// Removes a waiter from the queue, used when a thread stops waiting
+    /// without being notified.
+    pub fn remove(&self, waiter: &Rc<Waiter>) {
+        self.waiters
+            .borrow_mut()
+            .retain(|other| !Rc::ptr_eq(other, waiter));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.borrow().is_empty()
+    }
+}
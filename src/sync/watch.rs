@@ -0,0 +1,288 @@
+//! A single-producer, multi-consumer channel that only retains the last
+//! sent value.
+//!
+//! This is useful for broadcasting state, such as configuration, which
+//! receivers only care about the latest version of. Receivers can park their
+//! green thread until the value changes with [`Receiver::changed`], and read it
+//! at any time with [`Receiver::borrow`].
+//!
+//! # Examples
+//!
+//! ```
+//! use pneuma::sync::watch;
+//! use pneuma::thread;
+//!
+//! let (tx, mut rx) = watch::channel("hello");
+//!
+//! let handle = thread::spawn(move || {
+//!     while rx.changed().is_ok() {
+//!         println!("received = {:?}", *rx.borrow());
+//!     }
+//! });
+//!
+//! tx.send("world").unwrap();
+//! drop(tx);
+//! handle.join();
+//! ```
+
+use std::cell::{Cell, Ref, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+pub use std::sync::mpsc::{RecvError, SendError};
+
+use super::wait_queue::WaitQueue;
+
+struct Shared<T> {
+    value: RefCell<T>,
+    /// Incremented every time a value is sent.
+    version: Cell<u64>,
+    sender: Cell<bool>,
+    receivers: Cell<usize>,
+    waiters: WaitQueue,
+}
+
+/// Sends values to the associated [`Receiver`]s.
+///
+/// This half is created by the [`channel`] function.
+pub struct Sender<T> {
+    shared: Rc<Shared<T>>,
+}
+
+/// Receives values from the associated [`Sender`].
+///
+/// This half is created by the [`channel`] function, and can be cloned.
+pub struct Receiver<T> {
+    shared: Rc<Shared<T>>,
+    /// The version of the last value seen by this receiver.
+    seen: u64,
+}
+
+/// Creates a new watch channel, returning the sender/receiver halves.
+///
+/// The receiver starts out having seen `init`, so [`Receiver::changed`] only
+/// returns once a new value is sent.
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(Shared {
+        value: RefCell::new(init),
+        version: Cell::new(0),
+        sender: Cell::new(true),
+        receivers: Cell::new(1),
+        waiters: WaitQueue::new("watch::Receiver"),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, seen: 0 },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Sends a new value, notifying all the receivers.
+    ///
+    /// # Errors
+    ///
+    /// Returns the value back if there are no receivers left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a receiver is holding a reference returned by
+    /// [`Receiver::borrow`].
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        if self.shared.receivers.get() == 0 {
+            return Err(SendError(t));
+        }
+        self.send_replace(t);
+        Ok(())
+    }
+
+    /// Sends a new value, returning the previous one. Unlike [`send`], this
+    /// succeeds even if there are no receivers.
+    ///
+    /// [`send`]: Sender::send
+    pub fn send_replace(&self, t: T) -> T {
+        crate::thread::consume_budget();
+        let old = self.shared.value.replace(t);
+        self.notify();
+        old
+    }
+
+    /// Modifies the value in place, notifying all the receivers.
+    pub fn send_modify<F>(&self, f: F)
+    where
+        F: FnOnce(&mut T),
+    {
+        crate::thread::consume_budget();
+        f(&mut self.shared.value.borrow_mut());
+        self.notify();
+    }
+
+    /// Returns a reference to the latest value sent.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.shared.value.borrow()
+    }
+
+    /// Creates a new receiver that has seen the latest value.
+    pub fn subscribe(&self) -> Receiver<T> {
+        self.shared.receivers.set(self.shared.receivers.get() + 1);
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.shared.version.get(),
+        }
+    }
+
+    /// Returns the number of receivers.
+    pub fn receiver_count(&self) -> usize {
+        self.shared.receivers.get()
+    }
+
+    /// Returns `true` if all the receivers have been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.receiver_count() == 0
+    }
+
+    fn notify(&self) {
+        self.shared.version.set(self.shared.version.get() + 1);
+        self.shared.waiters.notify_all();
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Returns a reference to the latest value, without marking it as seen.
+    ///
+    /// The reference must not be held while the thread parks, as the sender
+    /// will panic if it tries to send a new value while it is held.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.shared.value.borrow()
+    }
+
+    /// Returns a reference to the latest value, marking it as seen.
+    ///
+    /// The reference must not be held while the thread parks, as the sender
+    /// will panic if it tries to send a new value while it is held.
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        self.seen = self.shared.version.get();
+        self.shared.value.borrow()
+    }
+
+    /// Returns whether a value was sent that this receiver hasn't seen yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecvError`] if the sender has been dropped.
+    pub fn has_changed(&self) -> Result<bool, RecvError> {
+        if !self.shared.sender.get() {
+            return Err(RecvError);
+        }
+        Ok(self.seen != self.shared.version.get())
+    }
+
+    /// Parks the current green thread until a value this receiver hasn't seen
+    /// is sent, and marks it as seen.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecvError`] if the sender is dropped before a new value
+    /// is sent.
+    pub fn changed(&mut self) -> Result<(), RecvError> {
+        crate::thread::consume_budget();
+        loop {
+            let version = self.shared.version.get();
+            if self.seen != version {
+                self.seen = version;
+                return Ok(());
+            }
+            if !self.shared.sender.get() {
+                return Err(RecvError);
+            }
+            self.shared.waiters.wait();
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Receiver<T> {
+        self.shared.receivers.set(self.shared.receivers.get() + 1);
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.sender.set(false);
+        self.shared.waiters.notify_all();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receivers.set(self.shared.receivers.get() - 1);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("value", &*self.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("value", &*self.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+#[test]
+fn changed_returns_once_a_value_is_sent() {
+    use pneuma::thread;
+
+    let (tx, mut rx) = channel(0);
+    let handle = thread::spawn(move || {
+        rx.changed().unwrap();
+        *rx.borrow()
+    });
+    thread::yield_now();
+    assert!(!handle.is_finished());
+    tx.send(1).unwrap();
+    assert_eq!(handle.join(), 1);
+}
+
+#[test]
+fn borrow_and_update_marks_the_value_as_seen() {
+    let (tx, mut rx) = channel(0);
+    assert_eq!(rx.has_changed(), Ok(false));
+    tx.send(1).unwrap();
+    assert_eq!(rx.has_changed(), Ok(true));
+    assert_eq!(*rx.borrow(), 1);
+    assert_eq!(rx.has_changed(), Ok(true));
+    assert_eq!(*rx.borrow_and_update(), 1);
+    assert_eq!(rx.has_changed(), Ok(false));
+}
+
+#[test]
+fn send_modify_notifies_the_receivers() {
+    let (tx, mut rx) = channel(vec![1]);
+    tx.send_modify(|value| value.push(2));
+    assert_eq!(rx.has_changed(), Ok(true));
+    rx.changed().unwrap();
+    assert_eq!(*rx.borrow(), [1, 2]);
+}
+
+#[test]
+fn changed_fails_once_the_sender_is_dropped() {
+    use pneuma::thread;
+
+    let (tx, mut rx) = channel(0);
+    let handle = thread::spawn(move || rx.changed());
+    thread::yield_now();
+    drop(tx);
+    assert_eq!(handle.join(), Err(RecvError));
+}
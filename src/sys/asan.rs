@@ -0,0 +1,83 @@
+//! AddressSanitizer fiber annotations.
+//!
+//! ASan tracks the stack of each thread to detect stack overflows and
+//! use-after-return, so every switch to another stack must be announced with
+//! `__sanitizer_start_switch_fiber` and completed with
+//! `__sanitizer_finish_switch_fiber` once running on the new stack.
+
+use std::cell::Cell;
+use std::ffi::c_void;
+use std::ptr::null_mut;
+
+use pneuma::thread::context::Lifecycle;
+use pneuma::thread::Thread;
+
+extern "C" {
+    fn __sanitizer_start_switch_fiber(
+        fake_stack_save: *mut *mut c_void,
+        bottom: *const c_void,
+        size: usize,
+    );
+    fn __sanitizer_finish_switch_fiber(
+        fake_stack_save: *mut c_void,
+        bottom_old: *mut *const c_void,
+        size_old: *mut usize,
+    );
+}
+
+thread_local! {
+    /// The bounds of the OS thread's own stack, as they are costly to get.
+    static OS_STACK: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// The fake stack of the thread that switched away, to be restored when it
+/// resumes.
+pub(crate) struct FakeStack(*mut c_void);
+
+/// Announces a switch to the stack of `next`.
+pub(crate) fn start_switch(next: &Thread) -> FakeStack {
+    let (bottom, size) = stack(next);
+    let mut fake_stack = FakeStack(null_mut());
+    unsafe { __sanitizer_start_switch_fiber(&mut fake_stack.0, bottom as *const c_void, size) };
+    fake_stack
+}
+
+/// Switches away from the current thread, which has finished and will never
+/// resume, to `next`.
+pub(crate) unsafe fn switch_finished(old: Thread, next: Thread) {
+    let (bottom, size) = stack(&next);
+    release_and_switch(old, next, bottom, size);
+}
+
+/// Announces the switch, releasing the fake stack of the current thread, then
+/// switches. Everything on the fake stack is gone after the announcement, so
+/// this must not keep anything there: its arguments are passed in registers
+/// and it never borrows them.
+#[inline(never)]
+unsafe fn release_and_switch(old: Thread, next: Thread, bottom: usize, size: usize) {
+    __sanitizer_start_switch_fiber(null_mut(), bottom as *const c_void, size);
+    super::switch_context(old, next);
+}
+
+/// Completes a switch, once running on the new stack. Threads running for the
+/// first time have no fake stack.
+pub(crate) fn finish_switch(fake_stack: Option<FakeStack>) {
+    let fake_stack = fake_stack.map_or(null_mut(), |fake_stack| fake_stack.0);
+    unsafe { __sanitizer_finish_switch_fiber(fake_stack, null_mut(), null_mut()) };
+}
+
+/// Returns the lowest address and the size of the stack of `thread`.
+fn stack(thread: &Thread) -> (usize, usize) {
+    let cx = &thread.0;
+    if cx.lifecycle.get() != Lifecycle::OsThread {
+        return (cx.stack.data as usize, cx.stack.size);
+    }
+    OS_STACK.with(|os_stack| {
+        if let Some(bounds) = os_stack.get() {
+            return bounds;
+        }
+        let bounds = super::os_stack_bounds().map_or((0, 0), |bounds| (bounds.start, bounds.len()));
+        os_stack.set(Some(bounds));
+        bounds
+    })
+}
@@ -12,10 +12,34 @@
 // * x19-x29
 // * z8-z23 ?
 // * v8-v15 ?
+// * fpcr and fpsr, which aren't callee-saved but hold the rounding mode and
+//   exception flags of each thread
 
-__on_coroutine_exit: 
-    
+// Where a thread resumes once switched back to. The registers are already
+// restored, so it only returns to the caller of `switch_context`.
+.type      __on_coroutine_exit, "function"
+.p2align   4
+__on_coroutine_exit:
+    .cfi_startproc
     ret
+    .cfi_endproc
+.size      __on_coroutine_exit, .-__on_coroutine_exit
+
+// The root frame of every green thread, which calls `call_function` with the
+// arguments of `switch_context`. The return address is marked undefined and
+// the frame pointer is null, so both unwinders and frame pointer walks stop
+// here instead of wandering into the stack of the thread that switched.
+.type      __green_thread_start, "function"
+.p2align   4
+__green_thread_start:
+    .cfi_startproc
+    .cfi_undefined x30
+    mov x29, xzr
+    blr x3
+    // `call_function` never returns.
+    brk #0x1
+    .cfi_endproc
+.size      __green_thread_start, .-__green_thread_start
 
 // #[repr(C)]
 // pub struct Registers 
@@ -32,6 +56,10 @@ __on_coroutine_exit:
 .p2align   4
 
 switch_context:
+    // The registers are stored in the contexts rather than on the stack, so
+    // the default rules (CFA = sp, return address = x30) hold until the next
+    // context's frame is loaded.
+    .cfi_startproc
     // # Store context
     // store sp and function
     mov x2, sp
@@ -58,9 +86,22 @@ switch_context:
     stp d12, d13, [x0, #152]
     stp d14, d15, [x0, #168]
 
+    // store the floating-point control and status registers, so the rounding
+    // mode and exception flags of a thread don't leak into the others
+    mrs x9, fpcr
+    mrs x10, fpsr
+    stp x9, x10, [x0, #184]
+
     // # Load context
-    // General purpose registers
+    // Load sp and function, then the frame pointer and link so that from
+    // there on the unwind rules describe the next thread.
+    ldp x2, x3, [x1, #0]
     ldp x29, x30, [x1, #24]
+    .cfi_def_cfa x2, 0
+    mov sp, x2
+    .cfi_def_cfa_register sp
+
+    // General purpose registers
     ldp x27, x28, [x1, #40]
     ldp x25, x26, [x1, #56]
     ldp x23, x24, [x1, #72]
@@ -72,48 +113,41 @@ switch_context:
     ldp d10, d11, [x1, #136]
     ldp d12, d13, [x1, #152]
     ldp d14, d15, [x1, #168]
-    
-    // load sp and function
-    ldp x2, x3, [x1, #0]
-    mov sp, x2
 
-    // check if x29 has been initialized
-    cbnz x29, jump_to_new_context    
-    
-    // initialize LN and FP to return to the parent original coroutine.
-    ldp x29, x30, [x0, #24]
-
-    //adr x29, __on_coroutine_exit
-    
-jump_to_new_context:
+    // load the floating-point control and status registers, which are zeroed
+    // (the default state) for new threads
+    ldp x9, x10, [x1, #184]
+    msr fpcr, x9
+    msr fpsr, x10
 
+    // a null frame pointer means the thread never ran
+    cbz x29, __green_thread_start
     br x3
-
-
-.global    switch_no_save
-.type      switch_no_save, "function"
+    .cfi_endproc
+.size      switch_context, .-switch_context
+
+// Calls `fun(arg)` on the stack ending at `top`. The caller's stack pointer is
+// kept in the frame pointer, which the CFA is defined from, so unwinders
+// continue from `fun` into the frames of the caller's stack.
+.global    call_on_stack
+.type      call_on_stack, "function"
 .p2align   4
-switch_no_save: 
-    // # Load context
-    // General purpose registers
-    ldp x29, x30, [x0, #24]
-    ldp x27, x28, [x0, #40]
-    ldp x25, x26, [x0, #56]
-    ldp x23, x24, [x0, #72]
-    ldp x21, x22, [x0, #88]
-    ldp x19, x20, [x0, #104]
-
-    // load d registers
-    ldp d8,  d9,  [x0, #120]
-    ldp d10, d11, [x0, #136]
-    ldp d12, d13, [x0, #152]
-    ldp d14, d15, [x0, #168]
-    
-    // load sp and function
-    ldp x2, x3, [x0, #0]
+call_on_stack:
+    .cfi_startproc
+    stp x29, x30, [sp, #-16]!
+    .cfi_def_cfa_offset 16
+    .cfi_offset x29, -16
+    .cfi_offset x30, -8
+    mov x29, sp
+    .cfi_def_cfa_register x29
     mov sp, x2
- 
-    // initialize LN and FP to return to the parent original coroutine.
-    ldp x29, x30, [x0, #24]
-
-    br x3
+    blr x1
+    mov sp, x29
+    .cfi_def_cfa_register sp
+    ldp x29, x30, [sp], #16
+    .cfi_def_cfa_offset 0
+    .cfi_restore x29
+    .cfi_restore x30
+    ret
+    .cfi_endproc
+.size      call_on_stack, .-call_on_stack
@@ -0,0 +1,131 @@
+// # Calling convention
+// the following registers must be preserved:
+// * rbx, rbp, rsp, r12-r15
+// * the control bits of mxcsr and the x87 control word
+// mxcsr is saved whole so the exception flags of a thread don't leak into
+// the others either.
+
+// #[repr(C)]
+// pub struct Registers
+//     pub sp: u64,        rsp, pointing to the return address
+//     pub fun: u64,       where the thread resumes
+//     pub arg: u64,
+//     pub frame: u64,     rbp
+//     pub link: u64,      the return address, for backtraces
+//     pub general: [u64; 59],
+//                         rbx, r12-r15, then mxcsr and the x87 control word
+//
+
+// Where a thread resumes once switched back to. The registers are already
+// restored, so it only returns to the caller of `switch_context`.
+.type      __on_coroutine_exit, @function
+.p2align   4
+__on_coroutine_exit:
+    .cfi_startproc
+    ret
+    .cfi_endproc
+.size      __on_coroutine_exit, .-__on_coroutine_exit
+
+// The root frame of every green thread, which calls `call_function` with the
+// arguments of `switch_context`. The return address is marked undefined and
+// the frame pointer is null, so both unwinders and frame pointer walks stop
+// here instead of wandering into the stack of the thread that switched.
+.type      __green_thread_start, @function
+.p2align   4
+__green_thread_start:
+    .cfi_startproc
+    .cfi_undefined rip
+    xor ebp, ebp
+    // new threads start with the default floating-point environment
+    ldmxcsr [rip + __default_mxcsr]
+    fldcw [rip + __default_fpu_cw]
+    call rax
+    // `call_function` never returns.
+    ud2
+    .cfi_endproc
+.size      __green_thread_start, .-__green_thread_start
+
+.section   .rodata
+.p2align   2
+__default_mxcsr:
+    .long 0x1f80
+__default_fpu_cw:
+    .short 0x037f
+.text
+
+.global    switch_context
+.type      switch_context, @function
+.p2align   4
+
+switch_context:
+    // The registers are stored in the contexts rather than on the stack, so
+    // the default rules (CFA = rsp + 8, return address at rsp) hold
+    // throughout, for the current thread and then for the next one.
+    .cfi_startproc
+    // # Store context
+    // store sp, function and link
+    mov [rdi], rsp
+    lea rax, [rip + __on_coroutine_exit]
+    mov [rdi + 8], rax
+    mov rax, [rsp]
+    mov [rdi + 32], rax
+
+    // General purpose registers
+    mov [rdi + 24], rbp
+    mov [rdi + 40], rbx
+    mov [rdi + 48], r12
+    mov [rdi + 56], r13
+    mov [rdi + 64], r14
+    mov [rdi + 72], r15
+
+    // store the floating-point control state
+    stmxcsr [rdi + 80]
+    fnstcw [rdi + 84]
+
+    // # Load context
+    // General purpose registers
+    mov rbp, [rsi + 24]
+    mov rbx, [rsi + 40]
+    mov r12, [rsi + 48]
+    mov r13, [rsi + 56]
+    mov r14, [rsi + 64]
+    mov r15, [rsi + 72]
+
+    // load sp and function
+    mov rsp, [rsi]
+    mov rax, [rsi + 8]
+
+    // threads that never ran don't resume through `__on_coroutine_exit`, and
+    // have no floating-point state to load
+    lea rcx, [rip + __on_coroutine_exit]
+    cmp rax, rcx
+    jne __green_thread_start
+
+    // load the floating-point control state
+    ldmxcsr [rsi + 80]
+    fldcw [rsi + 84]
+    jmp rax
+    .cfi_endproc
+.size      switch_context, .-switch_context
+
+// Calls `fun(arg)` on the stack ending at `top`. The caller's stack pointer is
+// kept in the frame pointer, which the CFA is defined from, so unwinders
+// continue from `fun` into the frames of the caller's stack.
+.global    call_on_stack
+.type      call_on_stack, @function
+.p2align   4
+call_on_stack:
+    .cfi_startproc
+    push rbp
+    .cfi_def_cfa_offset 16
+    .cfi_offset rbp, -16
+    mov rbp, rsp
+    .cfi_def_cfa_register rbp
+    mov rsp, rdx
+    call rsi
+    mov rsp, rbp
+    pop rbp
+    .cfi_def_cfa rsp, 8
+    ret
+    .cfi_endproc
+.size      call_on_stack, .-call_on_stack
@@ -4,14 +4,101 @@
 // #[cfg(target_arch = "aarch64")]
 // mod aarch64;
 
+use std::ops::Range;
+
 use pneuma::thread::Thread;
 
-use pneuma::thread::{RcContext};
+#[cfg(feature = "asan")]
+pub(crate) mod asan;
+#[cfg(feature = "valgrind")]
+pub(crate) mod valgrind;
 
-// #[cfg(all(target_family = "aarch64", target_os = "linux"))]
+#[cfg(target_arch = "aarch64")]
 std::arch::global_asm!(include_str!("asm/aarch64-linux.s"));
 
+#[cfg(target_arch = "x86_64")]
+std::arch::global_asm!(include_str!("asm/x86_64-linux.s"));
+
 extern "C" {
     pub(crate) fn switch_context(store: Thread, next: Thread);
-    pub(crate) fn switch_no_save(next: RcContext);
+    fn call_on_stack(arg: *mut u8, fun: unsafe extern "C" fn(*mut u8), top: usize);
+}
+
+/// Runs `f` on the stack ending at `top`, which must be 16-byte aligned and
+/// unused while `f` runs. Panics in `f` abort the process.
+pub(crate) unsafe fn on_stack<F: FnOnce()>(top: usize, f: F) {
+    unsafe extern "C" fn trampoline<F: FnOnce()>(f: *mut u8) {
+        let f = (*f.cast::<Option<F>>()).take().unwrap();
+        f();
+    }
+    let mut f = Some(f);
+    call_on_stack((&mut f as *mut Option<F>).cast(), trampoline::<F>, top);
+}
+
+/// Returns the bounds of the current OS thread's stack.
+pub(crate) fn os_stack_bounds() -> Option<Range<usize>> {
+    unsafe {
+        let mut attr = std::mem::zeroed();
+        if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+            return None;
+        }
+        let mut addr = std::ptr::null_mut();
+        let mut size = 0;
+        let res = libc::pthread_attr_getstack(&attr, &mut addr, &mut size);
+        libc::pthread_attr_destroy(&mut attr);
+        (res == 0).then(|| addr as usize..addr as usize + size)
+    }
+}
+
+#[cfg(test)]
+mod fenv {
+    extern "C" {
+        pub fn fegetround() -> i32;
+        pub fn fesetround(round: i32) -> i32;
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub const FE_TOWARDZERO: i32 = 0xc00000;
+    #[cfg(target_arch = "x86_64")]
+    pub const FE_TOWARDZERO: i32 = 0xc00;
+    pub const FE_TONEAREST: i32 = 0;
+
+    /// Returns whether the current rounding mode truncates, by adding three
+    /// quarters of an ulp to one.
+    pub fn truncates() -> bool {
+        let x = std::hint::black_box(1.0f64) + std::hint::black_box(f64::EPSILON * 0.75);
+        x == 1.0
+    }
+}
+
+#[test]
+fn rounding_mode_is_per_thread() {
+    use fenv::*;
+    use pneuma::thread;
+
+    let handle = thread::spawn(|| unsafe {
+        fesetround(FE_TOWARDZERO);
+        thread::yield_now();
+        assert_eq!(fegetround(), FE_TOWARDZERO);
+        assert!(truncates());
+    });
+    thread::yield_now();
+    assert_eq!(unsafe { fegetround() }, FE_TONEAREST);
+    assert!(!truncates());
+    handle.join();
+    assert_eq!(unsafe { fegetround() }, FE_TONEAREST);
+    assert!(!truncates());
+}
+
+#[test]
+fn new_threads_round_to_nearest() {
+    use fenv::*;
+    use pneuma::thread;
+
+    unsafe { fesetround(FE_TOWARDZERO) };
+    let rounding = thread::spawn(|| (unsafe { fegetround() }, truncates())).join();
+    let truncated = truncates();
+    unsafe { fesetround(FE_TONEAREST) };
+    assert_eq!(rounding, (FE_TONEAREST, false));
+    assert!(truncated);
 }
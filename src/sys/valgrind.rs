@@ -0,0 +1,53 @@
+//! Valgrind client requests, to tell valgrind about the green thread stacks.
+//!
+//! Without them, valgrind takes every context switch for a huge stack pointer
+//! change and reports the accesses to the new stack as invalid. The requests
+//! are magic instruction sequences that do nothing when the program doesn't
+//! run under valgrind, see `valgrind.h`.
+
+const STACK_REGISTER: usize = 0x1501;
+const STACK_DEREGISTER: usize = 0x1502;
+
+/// Registers the stack spanning `start..end`, returning its valgrind id.
+pub(crate) fn stack_register(start: usize, end: usize) -> usize {
+    unsafe { client_request(0, [STACK_REGISTER, start, end, 0, 0, 0]) }
+}
+
+/// Deregisters a stack registered with [`stack_register`].
+pub(crate) fn stack_deregister(id: usize) {
+    unsafe { client_request(0, [STACK_DEREGISTER, id, 0, 0, 0, 0]) };
+}
+
+/// Sends a request to valgrind, returning `default` if the program doesn't
+/// run under valgrind.
+#[cfg(target_arch = "aarch64")]
+unsafe fn client_request(default: usize, args: [usize; 6]) -> usize {
+    let result;
+    std::arch::asm!(
+        "ror x12, x12, #3",
+        "ror x12, x12, #13",
+        "ror x12, x12, #51",
+        "ror x12, x12, #61",
+        "orr x10, x10, x10",
+        inout("x3") default => result,
+        in("x4") args.as_ptr(),
+        options(nostack),
+    );
+    result
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn client_request(default: usize, args: [usize; 6]) -> usize {
+    let result;
+    std::arch::asm!(
+        "rol rdi, 3",
+        "rol rdi, 13",
+        "rol rdi, 61",
+        "rol rdi, 51",
+        "xchg rbx, rbx",
+        inout("rdx") default => result,
+        in("rax") args.as_ptr(),
+        options(nostack),
+    );
+    result
+}
@@ -1,8 +1,18 @@
+/// The mechanisms available to cancel a green thread, ordered by how
+/// aggressive they are.
+///
+/// Cancelling a thread more than once keeps the most aggressive mechanism
+/// requested. See the [module-level documentation](super#cancellation)
+/// for more details.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
 pub enum Cancel {
-    
-    /// To 
-    Flag, 
-    DisableIo, 
-    Unwind, 
-
-}
\ No newline at end of file
+    /// Only sets the cancellation flag, which the thread can check through
+    /// [`is_cancelled`](super::is_cancelled).
+    FlagOnly,
+    /// Causes all pending async io to yield immediately with an error.
+    DisableIo,
+    /// Causes the thread to unwind the next time it resumes from [`park`](super::park).
+    /// The panic payload is this enum variant, so it can be told apart from
+    /// other panics when joining the thread.
+    Unwind,
+}
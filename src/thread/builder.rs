@@ -1,10 +1,11 @@
 use std::io;
 
-use pneuma::thread::JoinHandle;
+use pneuma::thread::{JoinHandle, Priority, ThreadName};
 
 pub struct Builder {
-    pub(crate) name: Option<String>,
+    pub(crate) name: Option<ThreadName>,
     pub(crate) stack_size: usize,
+    pub(crate) priority: Priority,
 }
 
 impl Default for Builder {
@@ -23,7 +24,7 @@ impl Builder {
     /// use pneuma::thread;
     ///
     /// let builder = thread::Builder::new()
-    ///                               .name("foo".into())
+    ///                               .name("foo")
     ///                               .stack_size(2 * 1024);
     ///
     /// let handler = builder.spawn(|| {
@@ -37,13 +38,16 @@ impl Builder {
             name: None,
 
             stack_size: 1 << 14,
+            priority: Priority::Normal,
         }
     }
 
     /// Names the thread-to-be. Currently the name is used for identification
     /// only in panic messages.
     ///
-    /// The name must not contain null bytes (`\0`).
+    /// The name must not contain null bytes (`\0`), otherwise [`spawn`] returns
+    /// an error. Static and shared names, passed as a `&'static str` or an
+    /// [`Arc<str>`](std::sync::Arc), aren't copied, see [`ThreadName`].
     ///
     /// For more information about named threads, see
     /// [this module-level documentation][naming-threads].
@@ -54,7 +58,7 @@ impl Builder {
     /// use pneuma::thread;
     ///
     /// let builder = thread::Builder::new()
-    ///     .name("foo".into());
+    ///     .name("foo");
     ///
     /// let handler = builder.spawn(|| {
     ///     assert_eq!(thread::current().name(), Some("foo"))
@@ -62,9 +66,11 @@ impl Builder {
     ///
     /// handler.join().unwrap();
     /// ```
-    pub fn name(self, name: String) -> Self {
+    ///
+    /// [`spawn`]: Builder::spawn
+    pub fn name(self, name: impl Into<ThreadName>) -> Self {
         Self {
-            name: Some(name),
+            name: Some(name.into()),
             ..self
         }
     }
@@ -85,19 +91,90 @@ impl Builder {
         Self { stack_size, ..self }
     }
 
+    /// Sets the scheduling priority of the new thread.
+    ///
+    /// Threads are spawned with [`Priority::Normal`] by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread::{self, Priority};
+    ///
+    /// let handle = thread::Builder::new()
+    ///     .priority(Priority::High)
+    ///     .spawn(|| thread::current().priority())
+    ///     .unwrap();
+    ///
+    /// assert_eq!(handle.join(), Priority::High);
+    /// ```
+    pub fn priority(self, priority: Priority) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// Spawns a new green thread by taking ownership of the `Builder`, and
+    /// returns an [`io::Result`] to its [`JoinHandle`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`InvalidInput`](io::ErrorKind::InvalidInput) error if the
+    /// name contains null bytes, a [`WouldBlock`](io::ErrorKind::WouldBlock)
+    /// error if the [thread limit](crate::runtime::set_max_threads) is reached,
+    /// and the OS error if the stack or the thread can't be allocated.
     pub fn spawn<T, F>(self, f: F) -> io::Result<JoinHandle<T>>
     where
         F: FnOnce() -> T + 'static,
         T: 'static,
     {
+        if let Some(name) = &self.name {
+            validate_name(name)?;
+        }
         JoinHandle::new(f, self)
     }
 
     pub(crate) fn for_os_thread() -> Self {
         Builder {
-            name: std::thread::current().name().map(Into::into),
+            name: std::thread::current()
+                .name()
+                .map(|name| name.to_owned().into()),
 
             stack_size: 0,
+            priority: Priority::Normal,
         }
     }
 }
+
+pub(crate) fn validate_name(name: &str) -> io::Result<()> {
+    if name.contains('\0') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "thread name may not contain interior null bytes",
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn spawn_rejects_null_bytes() {
+    let err = Builder::new().name("foo\0").spawn(|| ()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn spawn_reports_mmap_failure() {
+    // No address space is large enough to map this stack.
+    let err = Builder::new().stack_size(1 << 62).spawn(|| ()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+    // The failed spawn doesn't count towards the thread limit.
+    crate::runtime::set_max_threads(Some(1));
+    Builder::new().spawn(|| ()).unwrap().join();
+}
+
+#[test]
+fn spawn_respects_max_threads() {
+    crate::runtime::set_max_threads(Some(1));
+    let handle = Builder::new().spawn(|| ()).unwrap();
+    let err = Builder::new().spawn(|| ()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    handle.join();
+    Builder::new().spawn(|| ()).unwrap().join();
+}
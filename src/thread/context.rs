@@ -1,8 +1,10 @@
-use pneuma::thread::RcContext;
-
-
+use pneuma::thread::{RcContext, Thread, ThreadId};
 
+use super::abort::Cancel;
 use super::builder::Builder;
+use super::name::ThreadName;
+use super::priority::Priority;
+use super::registry;
 use super::{registers::Registers, stack::Stack};
 use std::alloc::alloc;
 use std::alloc::Layout;
@@ -12,6 +14,7 @@ use std::cell::UnsafeCell;
 use std::io;
 use std::mem::zeroed;
 use std::ptr::NonNull;
+use std::task::Waker;
 
 /// The thread context as it was left before the switch.
 ///
@@ -23,11 +26,27 @@ use std::ptr::NonNull;
 pub(crate) struct Context {
     pub registers: UnsafeCell<Registers>,
     pub stack: Stack,
+    pub id: ThreadId,
     pub layout: Layout,
-    pub name: Option<String>,
+    /// The name the thread was spawned with, kept along with the context so
+    /// that it can be borrowed.
+    pub spawn_name: Option<ThreadName>,
+    /// The name given by [`Thread::set_name`], which replaces the spawn name.
+    pub renamed: Cell<Option<&'static str>>,
     pub lifecycle: Cell<Lifecycle>,
     pub status: Cell<Status>,
+    pub priority: Cell<Priority>,
     pub refcount: Cell<u64>,
+    pub cancel: Cell<Option<Cancel>>,
+    /// The primitive the thread is parked on, if any.
+    pub waiting_on: Cell<Option<&'static str>>,
+    /// The span the thread runs in, entered whenever it is switched in.
+    #[cfg(feature = "tracing")]
+    pub span: std::cell::OnceCell<tracing::Span>,
+    /// Woken up when the thread finishes, used to await the thread.
+    pub join_waker: Cell<Option<Waker>>,
+    /// The thread parked joining this thread.
+    pub joiner: Cell<Option<Thread>>,
     pub fun: *mut dyn FnMut(*mut ()),
     pub out: *mut dyn Any,
     // fun_alloc: impl FnMut(&mut Option<T>),
@@ -51,6 +70,11 @@ pub enum Lifecycle {
 }
 
 impl Context {
+    /// Returns the current name of the thread.
+    pub fn name(&self) -> Option<&str> {
+        self.renamed.get().or(self.spawn_name.as_deref())
+    }
+
     pub fn new<T, F>(fun: F, mut builder: Builder) -> io::Result<RcContext>
     where
         F: FnMut(*mut ()) + 'static,
@@ -59,8 +83,15 @@ impl Context {
         unsafe {
             let (layout, fun_offset, out_offset) = layout::<T, F>();
 
+            // The stack is mapped first so nothing has to be cleaned up if it fails.
+            let stack = Stack::new(builder.stack_size)?;
             let ptr = alloc(layout);
-            assert!(!ptr.is_null());
+            if ptr.is_null() {
+                return Err(io::Error::new(
+                    io::ErrorKind::OutOfMemory,
+                    "failed to allocate the thread context",
+                ));
+            }
 
             let fun_alloc = ptr.add(fun_offset) as *mut F;
             fun_alloc.write(fun);
@@ -72,10 +103,19 @@ impl Context {
 
             let cx = Context {
                 registers: zeroed(),
-                stack: Stack::new(builder.stack_size)?,
-                name: builder.name.take(),
+                stack,
+                id: ThreadId::new(),
+                spawn_name: builder.name.take(),
+                renamed: Cell::new(None),
                 refcount: 1.into(),
+                cancel: Cell::new(None),
+                waiting_on: Cell::new(None),
+                #[cfg(feature = "tracing")]
+                span: std::cell::OnceCell::new(),
+                join_waker: Cell::new(None),
+                joiner: Cell::new(None),
                 status: Cell::new(Status::Waiting),
+                priority: Cell::new(builder.priority),
                 fun: fun_alloc as *mut dyn FnMut(*mut ()),
                 lifecycle: Lifecycle::New.into(),
                 layout,
@@ -83,6 +123,7 @@ impl Context {
             };
             ptr.cast::<Context>().write(cx);
             let cx = RcContext(NonNull::new(ptr.cast()).unwrap());
+            registry::register(cx.0);
             Ok(cx.setup_registers())
         }
     }
@@ -1,11 +1,6 @@
-
-
 use super::Thread;
 use pneuma::runtime;
 
-
-
-
 /// Gets a handle to the thread that invokes it. The thread may be either a
 /// green thread or an os thread.
 ///
@@ -17,7 +12,7 @@ use pneuma::runtime;
 /// use pneuma::thread;
 ///
 /// let handler = thread::Builder::new()
-///     .name("named thread".into())
+///     .name("named thread")
 ///     .spawn(|| {
 ///         let handle = thread::current();
 ///         assert_eq!(handle.name(), Some("named thread"));
@@ -1,6 +1,13 @@
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use std::{any::Any, io, marker::PhantomData, panic::resume_unwind};
 
-use super::{builder::Builder, context::Lifecycle, RcContext, Thread};
+use super::{abort::Cancel, builder::Builder, context::Lifecycle, RcContext, Thread};
+use super::{current, park, park_timeout};
+use crate::runtime;
 
 /// An owned permission to join on a green thread (block on its termination).
 ///
@@ -64,7 +71,8 @@ use super::{builder::Builder, context::Lifecycle, RcContext, Thread};
 ///
 /// [`thread::Builder::spawn`]: Builder::spawn
 /// [`thread::spawn`]: spawn
-pub struct JoinHandle<T>(pub(crate) Thread, PhantomData<T>);
+// The output is stored in the thread's context, not in the handle itself.
+pub struct JoinHandle<T>(pub(crate) Thread, PhantomData<fn() -> T>);
 
 impl<T> JoinHandle<T> {
     pub(crate) fn new<F>(f: F, builder: Builder) -> io::Result<Self>
@@ -72,12 +80,60 @@ impl<T> JoinHandle<T> {
         F: FnOnce() -> T + 'static,
         T: 'static,
     {
-        let cx = RcContext::new(f, builder)?;
+        let runtime = runtime::current();
+        runtime.reserve_thread()?;
+        let cx = RcContext::new(f, builder).inspect_err(|_| runtime.release_thread())?;
         let thread = Thread(cx);
+        #[cfg(feature = "tracing")]
+        let _ = thread.0.span.set(tracing::trace_span!(
+            "green_thread",
+            id = thread.id().as_u64(),
+            name = thread.name(),
+        ));
+        if let Some(hooks) = &runtime.executor.hooks {
+            hooks.on_spawn(&thread);
+        }
         thread.unpark();
         Ok(JoinHandle(thread, PhantomData))
     }
 
+    /// Requests the cancellation of the associated thread.
+    ///
+    /// See [`Thread::cancel`] for details.
+    pub fn cancel(&self, how: Cancel) {
+        self.0.cancel(how)
+    }
+
+    /// Returns a handle to the underlying thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread;
+    ///
+    /// let handle = thread::spawn(|| {});
+    /// let thread = handle.thread().clone();
+    /// handle.join();
+    /// ```
+    pub fn thread(&self) -> &Thread {
+        &self.0
+    }
+
+    /// Checks if the associated thread has finished running its main function.
+    ///
+    /// This function does not park. If it returns `true`, [`join`] and
+    /// [`try_join_now`] return immediately.
+    ///
+    /// [`join`]: JoinHandle::join
+    /// [`try_join_now`]: JoinHandle::try_join_now
+    pub fn is_finished(&self) -> bool {
+        self.0 .0.lifecycle.get() == Lifecycle::Finished
+    }
+
+    /// Waits for the associated thread to finish, returning its output.
+    ///
+    /// The current green thread is parked until the thread finishes. If the
+    /// thread panicked, the panic is resumed on the current thread.
     pub fn join(self) -> T {
         match self.try_join() {
             Ok(out) => out,
@@ -85,17 +141,218 @@ impl<T> JoinHandle<T> {
         }
     }
 
+    /// Waits for the associated thread to finish, like [`join`], but returns
+    /// the panic payload as an [`Err`] if the thread panicked, like `std`'s
+    /// `join`.
+    ///
+    /// Note that this method parks until the thread finishes. For a method that
+    /// returns immediately, see [`try_join_now`].
+    ///
+    /// [`join`]: JoinHandle::join
+    /// [`try_join_now`]: JoinHandle::try_join_now
     pub fn try_join(self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        self.wait(None);
+        unsafe { self.take_output() }
+    }
+
+    /// Returns the output of the thread if it has finished, or the handle back
+    /// if it hasn't. This function never parks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread;
+    ///
+    /// let handle = thread::spawn(|| 1);
+    /// // the thread hasn't had a chance to run yet
+    /// let handle = handle.try_join_now().unwrap_err();
+    /// assert_eq!(handle.join(), 1);
+    /// ```
+    pub fn try_join_now(self) -> Result<Result<T, Box<dyn Any + Send + 'static>>, JoinHandle<T>> {
+        if !self.is_finished() {
+            return Err(self);
+        }
+        Ok(unsafe { self.take_output() })
+    }
+
+    /// Waits for the associated thread to finish for at most `timeout`,
+    /// returning the handle back if it didn't finish in time.
+    ///
+    /// If the thread panicked, the panic is resumed on the current thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread;
+    /// use std::time::Duration;
+    ///
+    /// let handle = thread::spawn(|| {
+    ///     thread::park_timeout(Duration::from_millis(100));
+    /// });
+    /// let handle = handle.join_timeout(Duration::from_millis(1)).unwrap_err();
+    /// handle.join();
+    /// ```
+    pub fn join_timeout(self, timeout: Duration) -> Result<T, JoinHandle<T>> {
+        if !self.wait(Instant::now().checked_add(timeout)) {
+            return Err(self);
+        }
+        match unsafe { self.take_output() } {
+            Ok(out) => Ok(out),
+            Err(err) => resume_unwind(err),
+        }
+    }
+
+    /// Parks the current thread until the associated thread finishes or the
+    /// deadline is reached. Returns whether the thread finished.
+    fn wait(&self, deadline: Option<Instant>) -> bool {
+        let cx = &self.0 .0;
         loop {
-            match self.0 .0.lifecycle.get() {
+            match cx.lifecycle.get() {
                 Lifecycle::Taken | Lifecycle::OsThread => unreachable!(),
-                Lifecycle::New | Lifecycle::Running => pneuma::thread::park(),
-                Lifecycle::Finished => unsafe {
-                    self.0 .0.lifecycle.set(Lifecycle::Taken);
-                    let out = self.0 .0.out as *mut Result<T, Box<dyn Any + Send + 'static>>;
-                    return out.read();
-                },
+                Lifecycle::Finished => return true,
+                Lifecycle::New | Lifecycle::Running => (),
+            }
+            let thread = current();
+            cx.joiner.set(Some(thread.clone()));
+            thread.set_waiting_on(Some("JoinHandle"));
+            match deadline {
+                None => park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        cx.joiner.take();
+                        thread.set_waiting_on(None);
+                        return false;
+                    }
+                    park_timeout(deadline - now);
+                }
+            }
+            thread.set_waiting_on(None);
+        }
+    }
+
+    /// Takes the output of the thread.
+    ///
+    /// # Safety
+    /// The thread must be finished, and its output must not have been taken.
+    unsafe fn take_output(&self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        self.0 .0.lifecycle.set(Lifecycle::Taken);
+        let out = self.0 .0.out as *mut Result<T, Box<dyn Any + Send + 'static>>;
+        out.read()
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle").finish_non_exhaustive()
+    }
+}
+
+/// A future that resolves once a green thread finishes, created by awaiting
+/// a [`JoinHandle`].
+///
+/// The future resolves to [`Ok`] with the output of the thread, or to [`Err`]
+/// with the panic payload if the thread panicked.
+///
+/// Polling never blocks: while the thread runs, the future registers its waker
+/// and returns [`Poll::Pending`], and the waker is woken up when the thread
+/// finishes.
+///
+/// When polled outside of a green thread, for example by another executor, the
+/// future first runs the green threads queued on the OS thread, as nothing else
+/// would. If they still have work left, such as a pending timer, it wakes its
+/// waker right away to be polled again. Within a green thread, and in
+/// [`block_on`](crate::block_on), the pneuma scheduler runs them instead.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// let handle = thread::spawn(|| 1 + 1);
+/// let out = pneuma::block_on(async { handle.await });
+/// assert_eq!(out.unwrap(), 2);
+/// ```
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct JoinFuture<T>(Option<JoinHandle<T>>);
+
+impl<T> IntoFuture for JoinHandle<T> {
+    type Output = Result<T, Box<dyn Any + Send + 'static>>;
+    type IntoFuture = JoinFuture<T>;
+
+    fn into_future(self) -> JoinFuture<T> {
+        JoinFuture(Some(self))
+    }
+}
+
+impl<T> Future for JoinFuture<T> {
+    type Output = Result<T, Box<dyn Any + Send + 'static>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let handle = self.0.as_ref().expect("JoinFuture polled after completion");
+        let rt = runtime::current();
+        let on_os_thread = rt.executor.current().id() == rt.executor.root.id();
+        if on_os_thread && handle.0 .0.lifecycle.get() != Lifecycle::Finished {
+            super::yield_now();
+        }
+        if handle.0 .0.lifecycle.get() != Lifecycle::Finished {
+            handle.0 .0.join_waker.set(Some(cx.waker().clone()));
+            if on_os_thread && rt.has_pending_work() {
+                cx.waker().wake_by_ref();
+            }
+            return Poll::Pending;
+        }
+        let handle = self.0.take().unwrap();
+        handle.0 .0.join_waker.take();
+        Poll::Ready(unsafe { handle.take_output() })
+    }
+}
+
+#[test]
+fn await_thread_that_parks() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let woken = Rc::new(Cell::new(false));
+    let flag = woken.clone();
+    let handle = pneuma::thread::spawn(move || {
+        while !flag.get() {
+            pneuma::thread::park();
+        }
+        7
+    });
+    let parked = handle.thread().clone();
+    let unparker = pneuma::thread::spawn(move || {
+        woken.set(true);
+        parked.unpark();
+    });
+    assert_eq!(pneuma::block_on(async { handle.await }).unwrap(), 7);
+    unparker.join();
+}
+
+#[test]
+fn await_thread_from_another_executor() {
+    use std::task::Waker;
+
+    fn poll_until_ready<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        for _ in 0..1000 {
+            if let Poll::Ready(out) = future.as_mut().poll(&mut cx) {
+                return out;
             }
         }
+        panic!("the future never resolved");
     }
+
+    let handle = pneuma::thread::spawn(|| 1 + 1);
+    assert_eq!(poll_until_ready(handle.into_future()).unwrap(), 2);
+
+    let handle = pneuma::thread::spawn(|| {
+        for _ in 0..10 {
+            pneuma::thread::yield_now();
+        }
+        3
+    });
+    assert_eq!(poll_until_ready(handle.into_future()).unwrap(), 3);
 }
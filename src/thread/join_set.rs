@@ -0,0 +1,294 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+
+use crate::sync::wait_queue::WaitQueue;
+
+use super::{Builder, Cancel, JoinHandle, Thread};
+
+/// A collection of green threads that can be joined in the order they finish.
+///
+/// Threads are added to the set with [`spawn`], and their results are retrieved
+/// with [`join_next`] as they finish. This is useful for fan-out/fan-in patterns,
+/// where the results are processed as soon as they are available rather than
+/// in the order the threads were spawned.
+///
+/// When the set is dropped, the threads that are still running are cancelled
+/// with [`Cancel::Unwind`], so they unwind the next time they park. They are
+/// otherwise detached, and are not joined.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread::{self, JoinSet};
+///
+/// let mut set = JoinSet::new();
+///
+/// for i in 0..10 {
+///     set.spawn(move || i);
+/// }
+///
+/// let mut seen = [false; 10];
+/// while let Some(res) = set.join_next() {
+///     let idx = res.unwrap();
+///     seen[idx] = true;
+/// }
+///
+/// assert!(seen.iter().all(|&seen| seen));
+/// ```
+///
+/// [`spawn`]: JoinSet::spawn
+/// [`join_next`]: JoinSet::join_next
+pub struct JoinSet<T> {
+    handles: HashMap<u64, JoinHandle<T>>,
+    next_key: u64,
+    shared: Rc<Shared>,
+}
+
+struct Shared {
+    /// The keys of the threads that have finished, in order.
+    finished: RefCell<VecDeque<u64>>,
+    waiters: WaitQueue,
+}
+
+/// Notifies the set when the thread finishes, even if it panics.
+struct Notify {
+    key: u64,
+    shared: Rc<Shared>,
+}
+
+impl<T> JoinSet<T> {
+    /// Creates an empty set.
+    pub fn new() -> JoinSet<T> {
+        JoinSet {
+            handles: HashMap::new(),
+            next_key: 0,
+            shared: Rc::new(Shared {
+                finished: RefCell::default(),
+                waiters: WaitQueue::new("JoinSet"),
+            }),
+        }
+    }
+
+    /// Returns the number of threads in the set that haven't been joined.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// Spawns a new green thread in the set, returning a handle to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread could not be spawned. Use [`spawn_with`] to handle
+    /// the error instead.
+    ///
+    /// [`spawn_with`]: JoinSet::spawn_with
+    pub fn spawn<F>(&mut self, f: F) -> Thread
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        self.spawn_with(Builder::new(), f).unwrap()
+    }
+
+    /// Spawns a new green thread in the set with the given configuration,
+    /// returning a handle to it.
+    pub fn spawn_with<F>(&mut self, builder: Builder, f: F) -> io::Result<Thread>
+    where
+        F: FnOnce() -> T + 'static,
+        T: 'static,
+    {
+        let key = self.next_key;
+        let notify = Notify {
+            key,
+            shared: self.shared.clone(),
+        };
+        let handle = builder.spawn(move || {
+            let _notify = notify;
+            f()
+        })?;
+        self.next_key += 1;
+        let thread = handle.thread().clone();
+        self.handles.insert(key, handle);
+        Ok(thread)
+    }
+
+    /// Waits for one of the threads in the set to finish, returning its output
+    /// or its panic payload if it panicked.
+    ///
+    /// Returns [`None`] if the set is empty.
+    pub fn join_next(&mut self) -> Option<Result<T, Box<dyn Any + Send + 'static>>> {
+        if self.handles.is_empty() {
+            return None;
+        }
+        loop {
+            if let Some(out) = self.try_join_next() {
+                return Some(out);
+            }
+            self.shared.waiters.wait();
+        }
+    }
+
+    /// Returns the output of a thread in the set that has finished, without
+    /// parking. Returns [`None`] if no thread has finished yet.
+    pub fn try_join_next(&mut self) -> Option<Result<T, Box<dyn Any + Send + 'static>>> {
+        loop {
+            let key = self.shared.finished.borrow_mut().pop_front()?;
+            if let Some(handle) = self.handles.remove(&key) {
+                // The thread notifies the set right before returning, so it
+                // may not be done yet.
+                return Some(handle.try_join());
+            }
+        }
+    }
+
+    /// Waits for all the threads in the set to finish, returning their outputs
+    /// in the order they finished.
+    ///
+    /// If any of the threads panicked, the panic is resumed on the current
+    /// thread once all threads are joined.
+    pub fn join_all(mut self) -> Vec<T> {
+        let mut outputs = Vec::with_capacity(self.len());
+        let mut panic = None;
+        while let Some(out) = self.join_next() {
+            match out {
+                Ok(out) => outputs.push(out),
+                Err(err) => panic = panic.or(Some(err)),
+            }
+        }
+        if let Some(panic) = panic {
+            std::panic::resume_unwind(panic);
+        }
+        outputs
+    }
+
+    /// Cancels all the threads in the set.
+    ///
+    /// The threads remain in the set, so they can still be joined.
+    pub fn cancel_all(&self, how: Cancel) {
+        for handle in self.handles.values() {
+            handle.cancel(how);
+        }
+    }
+
+    /// Removes all the threads from the set without cancelling them, so they
+    /// keep running in the background.
+    pub fn detach_all(&mut self) {
+        self.handles.clear();
+        self.shared.finished.borrow_mut().clear();
+    }
+}
+
+impl<T> Default for JoinSet<T> {
+    fn default() -> Self {
+        JoinSet::new()
+    }
+}
+
+impl<T> Drop for JoinSet<T> {
+    fn drop(&mut self) {
+        self.cancel_all(Cancel::Unwind);
+    }
+}
+
+impl<T> fmt::Debug for JoinSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinSet")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Notify {
+    fn drop(&mut self) {
+        self.shared.finished.borrow_mut().push_back(self.key);
+        self.shared.waiters.notify_one();
+    }
+}
+
+/// Waits for all the given threads to finish, returning their outputs in the
+/// same order.
+///
+/// If any of the threads panicked, the panic is resumed on the current thread
+/// once all threads are joined.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// let handles: Vec<_> = (0..4).map(|i| thread::spawn(move || i * 2)).collect();
+/// assert_eq!(thread::join_all(handles), [0, 2, 4, 6]);
+/// ```
+pub fn join_all<T, I>(handles: I) -> Vec<T>
+where
+    I: IntoIterator<Item = JoinHandle<T>>,
+{
+    let mut panic = None;
+    let outputs = handles
+        .into_iter()
+        .filter_map(|handle| match handle.try_join() {
+            Ok(out) => Some(out),
+            Err(err) => {
+                panic = panic.take().or(Some(err));
+                None
+            }
+        })
+        .collect();
+    if let Some(panic) = panic {
+        std::panic::resume_unwind(panic);
+    }
+    outputs
+}
+
+#[test]
+fn join_next_returns_threads_in_finish_order() {
+    let mut set = JoinSet::new();
+    for yields in [2, 0, 1] {
+        set.spawn(move || {
+            for _ in 0..yields {
+                super::yield_now();
+            }
+            yields
+        });
+    }
+    let order: Vec<_> = std::iter::from_fn(|| set.join_next())
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(order, [0, 1, 2]);
+}
+
+#[test]
+fn dropping_the_set_cancels_unfinished_threads() {
+    use std::cell::Cell;
+
+    struct Guard(Rc<Cell<bool>>);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    let unwound = Rc::new(Cell::new(false));
+    let mut set = JoinSet::new();
+    let guard = Guard(unwound.clone());
+    let thread = set.spawn(move || {
+        let _guard = guard;
+        loop {
+            super::park();
+        }
+    });
+    super::yield_now();
+    drop(set);
+    assert_eq!(thread.cancellation(), Some(Cancel::Unwind));
+    super::yield_now();
+    assert!(unwound.get());
+}
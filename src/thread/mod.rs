@@ -149,8 +149,14 @@
 //! [`thread_local!`]: crate::thread_local
 
 pub(crate) use context::Context;
-pub use join_handle::JoinHandle;
+pub use join_handle::{JoinFuture, JoinHandle};
+pub use join_set::{join_all, JoinSet};
 pub(crate) use rc_context::RcContext;
+use std::fmt;
+use std::io;
+use std::panic::resume_unwind;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 use std::{any::Any, cell::Cell};
 
 pub(crate) use stack::Stack;
@@ -160,21 +166,36 @@ pub use globals::current;
 
 use crate::runtime;
 
+pub use self::abort::Cancel;
 pub use self::builder::Builder;
-use self::context::Status;
+use self::context::{Lifecycle, Status};
+pub use self::name::ThreadName;
+pub use self::priority::Priority;
+pub(crate) mod abort;
 pub(crate) mod builder;
 pub(crate) mod globals;
 pub(crate) mod join_handle;
+pub(crate) mod join_set;
+pub(crate) mod name;
+pub(crate) mod priority;
 pub(crate) mod rc_context;
 pub(crate) mod registers;
+pub(crate) mod registry;
 pub(crate) mod stack;
 
+/// Spawns a new green thread, returning a [`JoinHandle`] for it.
+///
+/// # Panics
+///
+/// Panics if the thread can't be spawned, for example if its stack can't be
+/// mapped or the [thread limit](crate::runtime::set_max_threads) is reached.
+/// Use [`Builder::spawn`] to recover from these failures.
 pub fn spawn<T, F>(f: F) -> JoinHandle<T>
 where
     F: FnOnce() -> T + 'static,
     T: 'static,
 {
-    Builder::new().spawn(f).unwrap()
+    Builder::new().spawn(f).expect("failed to spawn thread")
 }
 
 /// Wait unless or until the current thread is unparked by someone calling [`Thread::unpark`].
@@ -191,7 +212,55 @@ where
 /// See also [`pneuma::thread::yield_now()`] for a function that yields once cooperatively and reschedules the
 /// thread immediately.
 pub fn park() {
-    runtime::current().park()
+    runtime::current().park();
+    unwind_if_cancelled();
+}
+
+/// Parks the current thread until it is unparked or until `dur` has elapsed.
+///
+/// Like [`park`], this function may return spuriously, so callers should check
+/// the condition they are waiting for and how much time has elapsed afterwards.
+///
+/// If no other thread is ready to run, the OS thread sleeps until the timeout
+/// expires.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+/// use std::time::{Duration, Instant};
+///
+/// let timeout = Duration::from_millis(10);
+/// let start = Instant::now();
+/// while start.elapsed() < timeout {
+///     thread::park_timeout(timeout - start.elapsed());
+/// }
+/// ```
+pub fn park_timeout(dur: Duration) {
+    runtime::current().park_timeout(dur);
+    unwind_if_cancelled();
+}
+
+/// Returns whether the current thread has been cancelled.
+///
+/// See the [module-level documentation](self#cancellation) for details.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// assert!(!thread::is_cancelled());
+/// ```
+pub fn is_cancelled() -> bool {
+    current().is_cancelled()
+}
+
+/// Unwinds the current thread if it was cancelled with [`Cancel::Unwind`].
+fn unwind_if_cancelled() {
+    if current().cancellation() == Some(Cancel::Unwind) && !std::thread::panicking() {
+        resume_unwind(Box::new(Cancel::Unwind));
+    }
 }
 
 /// Cooperatively gives up a timeslice to the pneuma scheduler.
@@ -232,13 +301,68 @@ pub fn park() {
 /// thread::yield_now();
 /// ```
 /// [`join`]: Thread::join
-/// [`Mutex`]: std::sync::Mutex
+/// [`Mutex`]: crate::sync::Mutex
 /// [`channel`]: std::sync::mpsc::channel
 pub fn yield_now() {
     current().unpark();
     park()
 }
 
+/// Yields if the current thread has used up its time slice.
+///
+/// This is a safe point for preemption: when preemption is enabled with
+/// [`runtime::Builder::preemption`], long running loops should call it
+/// regularly so the other threads get to run. It is cheap to call, and it does
+/// nothing when preemption is disabled.
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// let mut sum = 0u64;
+/// for i in 0..1_000_000 {
+///     sum += i;
+///     thread::checkpoint();
+/// }
+/// # assert!(sum > 0);
+/// ```
+pub fn checkpoint() {
+    if runtime::preempt::take_pending() {
+        yield_now();
+    }
+}
+
+/// Consumes one unit of the current thread's operation budget, yielding if it
+/// is exhausted.
+///
+/// The pneuma synchronization operations call this before they start, so green
+/// threads whose operations never need to park still give the other threads a
+/// chance to run. It can be called by custom blocking operations, such as IO wrappers,
+/// to take part in the budget. See [`runtime::Builder::budget`].
+///
+/// Unlike [`yield_now`], yielding here doesn't unwind a thread cancelled with
+/// [`Cancel::Unwind`].
+///
+/// # Examples
+///
+/// ```
+/// use pneuma::thread;
+///
+/// fn read_ready_socket() {
+///     thread::consume_budget();
+///     // ...
+/// }
+/// # read_ready_socket();
+/// ```
+pub fn consume_budget() {
+    let rt = runtime::current();
+    if !rt.executor.consume_budget() {
+        current().unpark();
+        rt.park();
+    }
+}
+
 #[derive(Clone)]
 #[repr(transparent)]
 pub struct Thread(pub(crate) RcContext);
@@ -246,13 +370,14 @@ pub struct Thread(pub(crate) RcContext);
 /// A unique identifier for a running thread.
 ///
 /// A `ThreadId` is an opaque object that uniquely identifies each thread
-/// created during the lifetime of a process. Unlinke std's `ThreadId`, Pneuma's
-/// `ThreadId`s may be reused after a thread terminates. A `ThreadId`
+/// created during the lifetime of a process. Like std's `ThreadId`, Pneuma's
+/// `ThreadId`s are never reused, even after a thread terminates, and they are
+/// unique across all the OS threads of the process. A `ThreadId`
 /// can be retrieved from the [`id`] method on a [`Thread`].
 ///
 /// # Examples
 ///
-/// ```ignore
+/// ```
 /// use pneuma::thread;
 ///
 /// let other_thread = thread::spawn(|| {
@@ -264,8 +389,43 @@ pub struct Thread(pub(crate) RcContext);
 /// ```
 ///
 /// [`id`]: Thread::id
-#[derive(PartialEq, PartialOrd, Eq, Ord, Clone, Copy)]
-pub struct ThreadId(usize);
+#[derive(PartialEq, PartialOrd, Eq, Ord, Clone, Copy, Hash, Debug)]
+#[repr(transparent)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    /// Generates a new unique thread id.
+    pub(crate) fn new() -> ThreadId {
+        static COUNTER: AtomicU64 = AtomicU64::new(1);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        assert!(
+            id != u64::MAX,
+            "failed to generate unique thread ID: bitspace exhausted"
+        );
+        ThreadId(id)
+    }
+
+    /// Returns the numeric value of the id. Ids are assigned in increasing
+    /// order, starting from one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread;
+    ///
+    /// let id = thread::current().id();
+    /// assert!(id.as_u64() > 0);
+    /// ```
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ThreadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
 
 impl Thread {
     /// Wakes up the thread to run in the future.
@@ -307,11 +467,21 @@ impl Thread {
         if thread.status.get() == Status::Queued {
             return;
         }
+        if let Lifecycle::Finished | Lifecycle::Taken = thread.lifecycle.get() {
+            return;
+        }
         thread.status.set(Status::Queued);
-        runtime::current().executor.push(self.clone());
+        let runtime = runtime::current();
+        if let Some(hooks) = &runtime.executor.hooks {
+            hooks.on_unpark(self);
+        }
+        runtime.executor.push(self.clone());
     }
     /// Gets the thread's name.
     ///
+    /// The name is returned as a shared handle, as the thread may be renamed
+    /// with [`set_name`](Thread::set_name) while it is held.
+    ///
     /// For more information about named threads, see
     /// [this module-level documentation][naming-threads].
     ///
@@ -337,7 +507,7 @@ impl Thread {
     /// use pneuma::thread;
     ///
     /// let builder = thread::Builder::new()
-    ///     .name("foo".into());
+    ///     .name("foo");
     ///
     /// let handler = builder.spawn(|| {
     ///     assert_eq!(thread::current().name(), Some("foo"))
@@ -348,18 +518,219 @@ impl Thread {
     ///
     /// [naming-threads]: ./index.html#naming-threads
     pub fn name(&self) -> Option<&str> {
-        self.0.name.as_deref()
+        self.0.name()
+    }
+
+    /// Renames the thread.
+    ///
+    /// This is useful for long-lived workers whose role changes over time.
+    /// Since the name returned by [`name`] may be borrowed while the thread is
+    /// renamed, new names other than `&'static str` are kept for the rest of
+    /// the process. Each distinct name costs memory once, however many times
+    /// threads are given it.
+    ///
+    /// [`name`]: Thread::name
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`InvalidInput`](io::ErrorKind::InvalidInput) error if the
+    /// name contains null bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread;
+    ///
+    /// let thread = thread::current();
+    /// thread.set_name("worker").unwrap();
+    /// assert_eq!(thread.name(), Some("worker"));
+    /// ```
+    pub fn set_name(&self, name: impl Into<ThreadName>) -> io::Result<()> {
+        let name = name.into();
+        builder::validate_name(&name)?;
+        self.0.renamed.set(Some(name::intern(name)));
+        Ok(())
+    }
+
+    /// Requests the cancellation of the thread, and unparks it so it can
+    /// observe it.
+    ///
+    /// See the [module-level documentation](self#cancellation) for details
+    /// on the available mechanisms.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread::{self, Cancel};
+    ///
+    /// let handle = thread::spawn(|| {
+    ///     while !thread::is_cancelled() {
+    ///         thread::park();
+    ///     }
+    /// });
+    /// thread::yield_now();
+    /// handle.cancel(Cancel::FlagOnly);
+    /// handle.join();
+    /// ```
+    pub fn cancel(&self, how: Cancel) {
+        let cancel = self.0.cancel.get().max(Some(how));
+        self.0.cancel.set(cancel);
+        self.unpark();
+    }
+
+    /// Returns whether the thread has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancel.get().is_some()
+    }
+
+    pub(crate) fn cancellation(&self) -> Option<Cancel> {
+        self.0.cancel.get()
+    }
+
+    /// Gets the thread's scheduling priority.
+    pub fn priority(&self) -> Priority {
+        self.0.priority.get()
+    }
+
+    /// Changes the thread's scheduling priority.
+    ///
+    /// If the thread is already queued to run, the new priority takes effect
+    /// the next time it is queued.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread::{self, Priority};
+    ///
+    /// let thread = thread::current();
+    /// thread.set_priority(Priority::High);
+    /// assert_eq!(thread.priority(), Priority::High);
+    /// ```
+    pub fn set_priority(&self, priority: Priority) {
+        self.0.priority.set(priority);
+    }
+
+    /// Records the primitive the thread is about to park on, reported in
+    /// deadlocks and dumps.
+    pub(crate) fn set_waiting_on(&self, what: Option<&'static str>) {
+        self.0.waiting_on.set(what);
     }
 
     pub(crate) fn status(&self) -> &Cell<Status> {
         &self.0.status
     }
 
+    /// Gets the thread's unique identifier.
+    ///
+    /// The id is assigned when the thread is created, and it stays the same
+    /// for the whole lifetime of the thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pneuma::thread;
+    ///
+    /// let handle = thread::spawn(|| thread::current().id());
+    /// let id = handle.thread().id();
+    /// assert_eq!(handle.join(), id);
+    /// ```
     pub fn id(&self) -> ThreadId {
-        ThreadId(self.0 .0.as_ptr() as usize)
+        self.0.id
     }
 
     pub(crate) fn for_os_thread() -> Thread {
         Thread(RcContext::for_os_thread())
     }
 }
+
+#[test]
+fn cancel_sets_the_flag() {
+    let handle = spawn(|| {
+        while !is_cancelled() {
+            park();
+        }
+    });
+    yield_now();
+    assert!(!handle.thread().is_cancelled());
+    handle.cancel(Cancel::FlagOnly);
+    assert!(handle.thread().is_cancelled());
+    handle.join();
+}
+
+#[test]
+fn threads_can_cancel_themselves() {
+    let handle = spawn(|| {
+        current().cancel(Cancel::FlagOnly);
+        is_cancelled()
+    });
+    assert!(handle.join());
+    // the thread queued itself when cancelling, and must not be resumed
+    yield_now();
+}
+
+#[test]
+fn cancel_unwinds_parked_threads() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let dropped = Rc::new(Cell::new(false));
+    let guard = Guard(dropped.clone());
+    let handle = spawn(move || {
+        let _guard = guard;
+        loop {
+            park();
+        }
+    });
+    yield_now();
+    // the most aggressive mechanism requested is kept
+    handle.cancel(Cancel::Unwind);
+    handle.cancel(Cancel::FlagOnly);
+    let payload = handle.try_join().unwrap_err();
+    assert_eq!(payload.downcast_ref::<Cancel>(), Some(&Cancel::Unwind));
+    assert!(dropped.get());
+
+    struct Guard(Rc<Cell<bool>>);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+}
+
+#[test]
+fn thread_ids_are_not_reused() {
+    let mut ids = vec![current().id()];
+    for _ in 0..3 {
+        // the thread is freed before the next one is spawned, so its
+        // context's memory can be reused, but not its id
+        let handle = spawn(|| current().id());
+        let id = handle.thread().id();
+        assert_eq!(handle.join(), id);
+        ids.push(id);
+    }
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "{ids:?}");
+}
+
+#[test]
+fn set_name_replaces_the_name() {
+    use std::sync::Arc;
+
+    let shared: Arc<str> = Arc::from("shared");
+    let handle = Builder::new().name(shared.clone()).spawn(|| ()).unwrap();
+    let thread = handle.thread().clone();
+    // shared and static names aren't copied
+    assert!(std::ptr::eq(thread.name().unwrap(), &*shared));
+    let name = "static";
+    thread.set_name(name).unwrap();
+    assert!(std::ptr::eq(thread.name().unwrap(), name));
+
+    thread.set_name(String::from("renamed")).unwrap();
+    let renamed = thread.name().unwrap();
+    assert_eq!(renamed, "renamed");
+    assert!(thread.set_name("bad\0name").is_err());
+    assert_eq!(thread.name(), Some("renamed"));
+    // giving a thread a name it had before doesn't copy it again
+    thread.set_name(Arc::<str>::from("renamed")).unwrap();
+    assert!(std::ptr::eq(thread.name().unwrap(), renamed));
+    handle.join();
+}
@@ -0,0 +1,97 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The name of a green thread, see [`Builder::name`](super::Builder::name)
+/// and [`Thread::set_name`](super::Thread::set_name).
+///
+/// A name can be created from a `&'static str`, a [`String`], a
+/// [`Cow<'static, str>`](Cow) or an [`Arc<str>`], which it keeps as is, so
+/// static and shared names are never copied.
+#[derive(Clone)]
+pub struct ThreadName(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Static(&'static str),
+    Owned(String),
+    Shared(Arc<str>),
+}
+
+impl Deref for ThreadName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match &self.0 {
+            Repr::Static(name) => name,
+            Repr::Owned(name) => name,
+            Repr::Shared(name) => name,
+        }
+    }
+}
+
+impl From<&'static str> for ThreadName {
+    fn from(name: &'static str) -> Self {
+        ThreadName(Repr::Static(name))
+    }
+}
+
+impl From<String> for ThreadName {
+    fn from(name: String) -> Self {
+        ThreadName(Repr::Owned(name))
+    }
+}
+
+impl From<Cow<'static, str>> for ThreadName {
+    fn from(name: Cow<'static, str>) -> Self {
+        match name {
+            Cow::Borrowed(name) => name.into(),
+            Cow::Owned(name) => name.into(),
+        }
+    }
+}
+
+impl From<Arc<str>> for ThreadName {
+    fn from(name: Arc<str>) -> Self {
+        ThreadName(Repr::Shared(name))
+    }
+}
+
+impl fmt::Debug for ThreadName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl fmt::Display for ThreadName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// The names given to threads when they are renamed.
+///
+/// The name of a thread can be borrowed from any of its handles, and another
+/// handle may rename it while the borrow lives, so these names are kept for
+/// the rest of the process. Renaming threads costs memory only the first time
+/// a name is used.
+static RENAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Returns a copy of `name` that lives for the rest of the process.
+pub(crate) fn intern(name: ThreadName) -> &'static str {
+    if let Repr::Static(name) = name.0 {
+        return name;
+    }
+    let mut renames = RENAMES.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(&interned) = renames.get(&*name) {
+        return interned;
+    }
+    let interned = match name.0 {
+        Repr::Owned(name) => name.leak(),
+        _ => String::from(&*name).leak(),
+    };
+    renames.insert(interned);
+    interned
+}
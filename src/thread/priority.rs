@@ -0,0 +1,31 @@
+/// The scheduling priority of a green thread.
+///
+/// Priorities are only used by the
+/// [`Prioritized`](crate::runtime::scheduler::Prioritized) scheduler, which
+/// must be installed with
+/// [`Builder::scheduler`](crate::runtime::Builder::scheduler) as the default
+/// one runs the threads in the order they were unparked. With it, runnable
+/// threads are served from the highest priority level first. To keep
+/// busy high priority threads from starving the others, threads that have been
+/// waiting in the queue for a while are served as if they had a higher
+/// priority. See [`Builder::priority`](super::Builder::priority) and
+/// [`Thread::set_priority`](super::Thread::set_priority).
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Clone, Copy)]
+pub enum Priority {
+    /// For background work that can wait for the other threads.
+    Low,
+    /// The priority threads are spawned with by default.
+    #[default]
+    Normal,
+    /// For latency sensitive threads.
+    High,
+}
+
+impl Priority {
+    /// The number of priority levels.
+    pub(crate) const LEVELS: usize = 3;
+
+    pub(crate) fn level(self) -> usize {
+        self as usize
+    }
+}
@@ -6,13 +6,13 @@ use std::{
     ptr::NonNull,
 };
 
-use pneuma::sys;
-
+use crate::runtime::{self, panic::on_thread_panic};
 use crate::thread::{park, Thread};
 
 use super::{
     builder::Builder,
     context::{Context, Lifecycle},
+    registry,
 };
 use std::alloc::dealloc;
 
@@ -34,6 +34,9 @@ impl RcContext {
         let fun = move |out: *mut ()| {
             let closure = f.take().unwrap();
             let res = catch_unwind(AssertUnwindSafe(closure));
+            if let Err(payload) = &res {
+                on_thread_panic(&super::current(), &**payload);
+            }
             unsafe {
                 out.cast::<Result<T, Box<dyn Any + Send + 'static>>>()
                     .write(res)
@@ -55,18 +58,34 @@ impl RcContext {
     }
 
     pub extern "C" fn call_function(link: RcContext, current: RcContext) {
+        #[cfg(feature = "asan")]
+        crate::sys::asan::finish_switch(None);
         {
+            let runtime = runtime::current();
+            let thread = Thread(current);
+            let current = &thread.0;
             assert_eq!(current.lifecycle.get(), Lifecycle::New);
             let f = unsafe { current.fun.as_mut().unwrap() };
             current.lifecycle.set(Lifecycle::Running);
+            if let Some(hooks) = &runtime.executor.hooks {
+                hooks.on_first_run(&thread);
+            }
             f(current.out.cast());
             current.lifecycle.set(Lifecycle::Finished);
-            drop(current);
+            runtime.release_thread();
+            if let Some(hooks) = &runtime.executor.hooks {
+                hooks.on_finish(&thread);
+            }
+            if let Some(waker) = current.join_waker.take() {
+                waker.wake();
+            }
+            if let Some(joiner) = current.joiner.take() {
+                joiner.unpark();
+            }
+            drop(thread);
         }
-        link.switch_no_save();
-    }
-    pub fn switch_no_save(self) {
-        unsafe { sys::switch_no_save(self) }
+        drop(link);
+        runtime::current().executor.finish()
     }
 }
 
@@ -108,6 +127,12 @@ impl Drop for RcContext {
             Lifecycle::Finished => unsafe { self.out.drop_in_place() },
         }
 
+        registry::deregister(self);
+        if self.lifecycle.get() != Lifecycle::OsThread {
+            if let Some(hooks) = runtime::try_current().and_then(|rt| rt.executor.hooks.clone()) {
+                hooks.on_drop(self.id);
+            }
+        }
         unsafe {
             self.0.as_ptr().drop_in_place();
             dealloc(self.0.as_ptr().cast(), layout);
@@ -126,3 +151,24 @@ impl Deref for RcContext {
         unsafe { self.0.as_ref() }
     }
 }
+
+#[test]
+fn backtrace_ends_at_thread_entry() {
+    use std::backtrace::Backtrace;
+
+    // Symbolising takes more than the default stack.
+    let backtrace = pneuma::thread::Builder::new()
+        .stack_size(1 << 20)
+        .spawn(|| format!("{:#}", Backtrace::force_capture()))
+        .unwrap()
+        .join();
+    assert!(backtrace.contains("call_function"), "{backtrace}");
+    // The outermost frame is the root frame, not one of the spawner's. The
+    // unwinder may still report the undefined return address as unknown.
+    let outermost = backtrace
+        .lines()
+        .filter(|line| !line.ends_with("<unknown>"))
+        .rfind(|line| line.trim_start().starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap();
+    assert!(outermost.contains("__green_thread_start"), "{backtrace}");
+}
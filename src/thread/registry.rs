@@ -0,0 +1,31 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ptr::NonNull;
+
+use super::Context;
+
+thread_local! {
+    /// The contexts allocated on this OS thread, by id. Contexts register
+    /// themselves when they are created and deregister when they are dropped,
+    /// so the pointers are always valid.
+    static CONTEXTS: RefCell<BTreeMap<u64, NonNull<Context>>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+pub(crate) fn register(cx: NonNull<Context>) {
+    let id = unsafe { cx.as_ref() }.id.as_u64();
+    CONTEXTS.with(|contexts| contexts.borrow_mut().insert(id, cx));
+}
+
+pub(crate) fn deregister(cx: &Context) {
+    let _ = CONTEXTS.try_with(|contexts| contexts.borrow_mut().remove(&cx.id.as_u64()));
+}
+
+/// Calls `f` with every context allocated on the current OS thread, in the
+/// order they were created.
+pub(crate) fn for_each(mut f: impl FnMut(&Context)) {
+    let contexts =
+        CONTEXTS.with(|contexts| contexts.borrow().values().copied().collect::<Vec<_>>());
+    for cx in contexts {
+        f(unsafe { cx.as_ref() });
+    }
+}
@@ -1,14 +1,25 @@
+use std::cell::Cell;
 use std::io;
 use std::{mem::zeroed, os::raw::c_void, ptr::null_mut};
 
 thread_local! {
     static PAGE_SIZE: usize = unsafe { libc::sysconf(libc::_SC_PAGE_SIZE) as usize};
+    /// The number of bytes mapped for stacks on this OS thread.
+    static MAPPED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Returns the number of bytes mapped for the stacks of the current OS thread.
+pub(crate) fn mapped_bytes() -> usize {
+    MAPPED.with(Cell::get)
 }
 
 #[repr(C)]
 pub(crate) struct Stack {
     pub data: *mut c_void,
     pub size: usize,
+    /// The id valgrind knows the stack by.
+    #[cfg(feature = "valgrind")]
+    valgrind_id: usize,
 }
 
 impl Stack {
@@ -21,7 +32,7 @@ impl Stack {
     #[allow(unused_mut)]
     pub fn new(mut size: usize) -> io::Result<Stack> {
         if size == 0 {
-            return unsafe { zeroed() };
+            return Ok(unsafe { zeroed() });
         }
 
         let mut flags = libc::MAP_ANONYMOUS | libc::MAP_PRIVATE;
@@ -32,7 +43,11 @@ impl Stack {
         }
 
         let page_size = PAGE_SIZE.with(|s| *s);
-        size += page_size - size % page_size;
+        size = size
+            .checked_add(page_size - size % page_size)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "stack size is too large")
+            })?;
         let data = unsafe {
             libc::mmap(
                 null_mut(),
@@ -47,14 +62,23 @@ impl Stack {
         if data as i64 == -1 {
             return Err(io::Error::last_os_error());
         }
-        Ok(Stack { data, size })
+        MAPPED.with(|mapped| mapped.set(mapped.get() + size));
+        Ok(Stack {
+            data,
+            size,
+            #[cfg(feature = "valgrind")]
+            valgrind_id: crate::sys::valgrind::stack_register(data as usize, data as usize + size),
+        })
     }
 }
 
 impl Drop for Stack {
     fn drop(&mut self) {
         if !self.data.is_null() {
+            #[cfg(feature = "valgrind")]
+            crate::sys::valgrind::stack_deregister(self.valgrind_id);
             let _x = unsafe { libc::munmap(self.data, self.size) };
+            let _ = MAPPED.try_with(|mapped| mapped.set(mapped.get() - self.size));
         }
     }
 }